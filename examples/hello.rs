@@ -9,10 +9,14 @@ fn main() {
     let log_function = console.get_property("log");
 
     let message = JSString::new("HI WORLD!!!");
-    log_function.call_1_arg(&console, &message);
+    log_function.call_1_arg(&console, &message).unwrap();
+    console.call_method("log", &[&message, &message]).unwrap();
 
     console::log("LOGGING FROM THE CONSOLE");
 
-    eval("console.log('EVAL SEEMS TO WORK')");
+    eval("console.log('EVAL SEEMS TO WORK')").unwrap();
+
+    let href = JS_SELF.get_property("location").get_property("href");
+    console::log(&format!("Page location: {}", href));
     let v = kwasm::libraries::Instant::now();
 }
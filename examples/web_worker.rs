@@ -5,7 +5,14 @@ use kwasm::libraries::*;
 use kwasm::*;
 
 fn main() {
-    kwasm::web_worker::spawn(|| {
+    let worker = kwasm::web_worker::spawn(|| {
         console::log("In worker");
+        40 + 2
+    });
+
+    // The main thread can't block, so wait for the worker asynchronously.
+    spawn_local(async move {
+        let result = worker.join_async().await.unwrap();
+        console::log(&format!("Worker returned: {}", result));
     });
 }
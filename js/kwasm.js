@@ -3,14 +3,85 @@ function kwasm_stuff() {
     const decoder = new TextDecoder();
     const encoder = new TextEncoder();
 
-    var kwasm_js_objects = [null, self];
+    // The first entries are reserved and are never freed.
+    // 0 is the null handle, which is `null` when passed to Javascript.
+    // The rest must match the constants next to `JS_SELF` in `js_object.rs`.
+    var kwasm_js_objects = [null, self, undefined, null, true, false];
     var kwasm_js_objects_free_indices = [];
+    const KWASM_LAST_RESERVED_INDEX = 5;
+    const KWASM_UNDEFINED_INDEX = 2;
+    const KWASM_NULL_INDEX = 3;
+    const KWASM_TRUE_INDEX = 4;
+    const KWASM_FALSE_INDEX = 5;
+
+    // Returned to Wasm when a call throws.
+    // Wasm then retrieves the exception with `kwasm_take_exception`.
+    const KWASM_EXCEPTION_THROWN = 0xFFFFFFFF;
+    var kwasm_exception = 0;
+
+    // The value returned by the Rust closure that was most recently called.
+    var kwasm_closure_result = undefined;
+
+    // Closing or terminating a worker also terminates the workers it spawned,
+    // so a worker that's finished stays open until its children have exited.
+    var kwasm_live_children = 0;
+    var kwasm_closing = false;
+    var kwasm_trapped = false;
+
+    // Pointers to the `JoinState`s of the `web_worker` jobs running on this thread, innermost last.
+    // Wasm keeps each one alive until it's popped.
+    // If a job traps they're all marked as panicked so joining them doesn't wait forever.
+    var kwasm_join_states = [];
+
+    function kwasm_mark_jobs_panicked() {
+        const memory = new Int32Array(self.kwasm_memory.buffer);
+        for (const join_state_pointer of kwasm_join_states) {
+            const index = join_state_pointer / 4;
+            if (Atomics.compareExchange(memory, index, 0, 2) === 0) {
+                Atomics.notify(memory, index);
+            }
+        }
+        kwasm_join_states = [];
+    }
+
+    // Tells the thread that spawned this worker that it has exited and closes it,
+    // once any workers it spawned have exited.
+    function kwasm_close_worker(trapped) {
+        kwasm_closing = true;
+        kwasm_trapped = trapped;
+        if (kwasm_live_children === 0) {
+            postMessage({ kwasm_worker_exited: true, trapped: trapped });
+            close();
+        }
+    }
+
+    // Converts a value to a number for the `kwasm_get_js_object_value_*` imports.
+    // Passing a BigInt or Symbol to Wasm as a number throws, which would trap, so values are
+    // converted with `Number` first and values that can't be converted become NaN.
+    function kwasm_to_number(object) {
+        try {
+            return Number(object);
+        } catch (error) {
+            return NaN;
+        }
+    }
 
     self.kwasm_get_object = function (index) {
         return kwasm_js_objects[index];
     }
 
     self.kwasm_new_js_object = function (item) {
+        // These values have reserved indices so they never use a slot.
+        if (item === undefined) {
+            return KWASM_UNDEFINED_INDEX;
+        } else if (item === null) {
+            return KWASM_NULL_INDEX;
+        } else if (item === true) {
+            return KWASM_TRUE_INDEX;
+        } else if (item === false) {
+            return KWASM_FALSE_INDEX;
+        }
+
         let index = kwasm_js_objects_free_indices.pop();
         if (!index) {
             return kwasm_js_objects.push(item) - 1;
@@ -20,6 +91,73 @@ function kwasm_stuff() {
         }
     };
 
+    // Decodes a string from Wasm memory.
+    // The data is copied first because TextDecoder can't decode from shared memory.
+    function kwasm_decode_string(data, data_length) {
+        const string_data = new Uint8Array(self.kwasm_memory.buffer, data, data_length);
+        return decoder.decode(new Uint8Array(string_data));
+    }
+
+    // Looks up the objects for an array of u32 handles in Wasm memory.
+    function kwasm_get_args(arg_data_ptr, args_length) {
+        const args = new Uint32Array(self.kwasm_memory.buffer, arg_data_ptr, args_length);
+        // Convert to Array first because Uint32Array's version of map
+        // expects a typed array as the return value.
+        return Array.from(args).map(a => kwasm_js_objects[a]);
+    }
+
+    // Decodes the 16 byte tagged args encoded by `JSArg` in `js_arg.rs`.
+    function kwasm_get_typed_args(arg_data_ptr, args_length) {
+        const data_view = new DataView(self.kwasm_memory.buffer, arg_data_ptr, args_length * 16);
+        let args = new Array(args_length);
+        for (let i = 0; i < args_length; i++) {
+            const offset = i * 16;
+            const tag = data_view.getUint32(offset, true);
+            switch (tag) {
+                case 0: // Handle
+                    args[i] = kwasm_js_objects[data_view.getUint32(offset + 4, true)];
+                    break;
+                case 1: // f64
+                    args[i] = data_view.getFloat64(offset + 8, true);
+                    break;
+                case 2: // i32
+                    args[i] = data_view.getInt32(offset + 4, true);
+                    break;
+                case 3: // u32
+                    args[i] = data_view.getUint32(offset + 4, true);
+                    break;
+                case 4: // String
+                    args[i] = kwasm_decode_string(data_view.getUint32(offset + 4, true), data_view.getUint32(offset + 8, true));
+                    break;
+                default:
+                    throw new Error("Unknown kwasm JSArg tag: " + tag);
+            }
+        }
+        return args;
+    }
+
+    // Calls `f` with `this_object` as `this` and returns a handle to the result.
+    // `f` can also be the name of a method to look up on `this_object`.
+    // If the call throws, the exception is stored for `kwasm_take_exception`.
+    function kwasm_call_and_store(f, this_object, args) {
+        let result;
+        try {
+            if (typeof f === "string") {
+                f = this_object[f];
+            }
+            result = Reflect.apply(f, this_object, args);
+        } catch (e) {
+            kwasm_exception = self.kwasm_new_js_object(e);
+            return KWASM_EXCEPTION_THROWN;
+        }
+        // Returning nothing is passed to Wasm as the null handle.
+        if (result === undefined) {
+            return 0;
+        } else {
+            return self.kwasm_new_js_object(result);
+        }
+    }
+
     self.kwasm_pass_string_to_client = function (string) {
         // Unfortunately this can't write directly to Wasm memory (yet).
         // See this issue: https://github.com/whatwg/encoding/issues/172
@@ -32,7 +170,7 @@ function kwasm_stuff() {
 
     let kwasm_import_functions = {
         kwasm_free_js_object: function (index) {
-            if (index > 1) {
+            if (index > KWASM_LAST_RESERVED_INDEX) {
                 kwasm_js_objects[index] = null;
                 kwasm_js_objects_free_indices.push(index);
             }
@@ -47,59 +185,289 @@ function kwasm_stuff() {
         // This expects that the function will return a u32.
         kwasm_call_js_with_args_raw: function (function_object, this_object, arg_data_ptr, args_length) {
             const args = new Uint32Array(self.kwasm_memory.buffer, arg_data_ptr, args_length);
-            let f = kwasm_js_objects[function_object];
-            let this_object0 = kwasm_js_objects[this_object];
-            let result = f.call(this_object0, ...args);
-            if (result == undefined) {
-                return 0;
-            } else {
-                return self.kwasm_new_js_object(result);
-            }
-            result
+            return kwasm_call_and_store(kwasm_js_objects[function_object], kwasm_js_objects[this_object], args);
         },
         kwasm_call_js_with_args: function (function_object, this_object, arg_data_ptr, args_length) {
-            const args = new Uint32Array(self.kwasm_memory.buffer, arg_data_ptr, args_length);
-            let f = kwasm_js_objects[function_object];
-            let this_object0 = kwasm_js_objects[this_object];
-            // Convert to Array first because Uint32Array's version of map
-            // expects a typed array as the return value.
-            let args0 = Array.from(args);
-            let args1 = args0.map(a => kwasm_js_objects[a]);
-            let result = f.call(this_object0, ...args1);
-            if (result == undefined) {
-                return 0;
-            } else {
-                return self.kwasm_new_js_object(result);
+            const args = kwasm_get_args(arg_data_ptr, args_length);
+            return kwasm_call_and_store(kwasm_js_objects[function_object], kwasm_js_objects[this_object], args);
+        },
+        kwasm_call_js_method: function (object_index, method_data, method_length, arg_data_ptr, args_length) {
+            const method_name = kwasm_decode_string(method_data, method_length);
+            const args = kwasm_get_args(arg_data_ptr, args_length);
+            return kwasm_call_and_store(method_name, kwasm_js_objects[object_index], args);
+        },
+        kwasm_call_js_with_typed_args: function (function_object, this_object, arg_data_ptr, args_length) {
+            const args = kwasm_get_typed_args(arg_data_ptr, args_length);
+            return kwasm_call_and_store(kwasm_js_objects[function_object], kwasm_js_objects[this_object], args);
+        },
+        kwasm_call_js_method_with_typed_args: function (object_index, method_data, method_length, arg_data_ptr, args_length) {
+            const method_name = kwasm_decode_string(method_data, method_length);
+            const args = kwasm_get_typed_args(arg_data_ptr, args_length);
+            return kwasm_call_and_store(method_name, kwasm_js_objects[object_index], args);
+        },
+        kwasm_construct_js_object: function (constructor_index, arg_data_ptr, args_length) {
+            let constructor = kwasm_js_objects[constructor_index];
+            let args = kwasm_get_args(arg_data_ptr, args_length);
+            let result;
+            try {
+                result = Reflect.construct(constructor, args);
+            } catch (e) {
+                kwasm_exception = self.kwasm_new_js_object(e);
+                return KWASM_EXCEPTION_THROWN;
             }
+            return self.kwasm_new_js_object(result);
+        },
+        kwasm_new_object: function () {
+            return self.kwasm_new_js_object({});
+        },
+        kwasm_new_array: function () {
+            return self.kwasm_new_js_object([]);
         },
         kwasm_js_object_property: function (object_index, property_name_index) {
             let object = kwasm_js_objects[object_index];
             let property_name = kwasm_js_objects[property_name_index];
-            let property_object = object[property_name];
-            if (property_object == undefined) {
-                console.log(object + " does not have property: " + property_name);
+            // A missing property is the null handle,
+            // a property that is present but `undefined` is the reserved `undefined` handle.
+            if (!(property_name in Object(object))) {
                 return 0;
-            } else {
-                return self.kwasm_new_js_object(property_object);
             }
+            return self.kwasm_new_js_object(object[property_name]);
         },
         // Returns this value as a u32
         kwasm_get_js_object_value_u32: function (object_index) {
-            return kwasm_js_objects[object_index];
+            let object = kwasm_js_objects[object_index];
+            if (typeof object === "bigint") {
+                return Number(BigInt.asUintN(32, object));
+            }
+            return kwasm_to_number(object);
         },
         // Returns this value as a f64
         kwasm_get_js_object_value_f64: function (object_index) {
-            return kwasm_js_objects[object_index];
+            return kwasm_to_number(kwasm_js_objects[object_index]);
+        },
+        kwasm_get_js_object_value_bool: function (object_index) {
+            return kwasm_js_objects[object_index] ? 1 : 0;
+        },
+        kwasm_get_js_object_value_i32: function (object_index) {
+            let object = kwasm_js_objects[object_index];
+            if (typeof object === "bigint") {
+                return Number(BigInt.asIntN(32, object));
+            }
+            return kwasm_to_number(object);
+        },
+        // Returns this value as an i64, which is passed to Wasm as a BigInt.
+        kwasm_get_js_object_value_i64: function (object_index) {
+            let object = kwasm_js_objects[object_index];
+            if (typeof object === "bigint") {
+                return BigInt.asIntN(64, object);
+            }
+            let number = kwasm_to_number(object);
+            if (!Number.isFinite(number)) {
+                return 0n;
+            }
+            return BigInt.asIntN(64, BigInt(Math.trunc(number)));
+        },
+        // Returns the type of a value. Must match the order of `JSType`.
+        kwasm_js_object_type_of: function (object_index) {
+            let object = kwasm_js_objects[object_index];
+            if (object === null) {
+                return 1;
+            }
+            switch (typeof object) {
+                case "undefined": return 0;
+                case "boolean": return 2;
+                case "number": return 3;
+                case "bigint": return 4;
+                case "string": return 5;
+                case "symbol": return 6;
+                case "function": return 8;
+                default: return 7;
+            }
+        },
+        // Passes the value to the client if it's a string.
+        // Returns 0 if the value is not a string.
+        kwasm_get_js_object_value_string: function (object_index) {
+            let object = kwasm_js_objects[object_index];
+            if (typeof object !== "string") {
+                return 0;
+            }
+            self.kwasm_pass_string_to_client(object);
+            return 1;
+        },
+        // Passes `String(value)` to the client.
+        kwasm_js_object_to_string: function (object_index) {
+            let object = kwasm_js_objects[object_index];
+            let string;
+            try {
+                string = String(object);
+            } catch (e) {
+                // Objects with a throwing or missing `toString`.
+                string = Object.prototype.toString.call(object);
+            }
+            self.kwasm_pass_string_to_client(string);
+        },
+        // Passes a property as a string to the client.
+        // Returns 0 if the property is `undefined` or `null`.
+        kwasm_js_object_property_to_string: function (object_index, property_data, property_length) {
+            let object = kwasm_js_objects[object_index];
+            const property_name = kwasm_decode_string(property_data, property_length);
+            let property;
+            try {
+                property = object[property_name];
+            } catch (e) {
+                return 0;
+            }
+            if (property == undefined) {
+                return 0;
+            }
+            self.kwasm_pass_string_to_client(String(property));
+            return 1;
+        },
+        kwasm_set_js_object_property: function (object_index, property_data, property_length, value_index) {
+            let object = kwasm_js_objects[object_index];
+            const property_name = kwasm_decode_string(property_data, property_length);
+            try {
+                object[property_name] = kwasm_js_objects[value_index];
+            } catch (e) {
+                kwasm_exception = self.kwasm_new_js_object(e);
+                return KWASM_EXCEPTION_THROWN;
+            }
+            return 0;
+        },
+        kwasm_delete_js_object_property: function (object_index, property_data, property_length) {
+            let object = kwasm_js_objects[object_index];
+            const property_name = kwasm_decode_string(property_data, property_length);
+            try {
+                return Reflect.deleteProperty(Object(object), property_name) ? 1 : 0;
+            } catch (e) {
+                return 0;
+            }
+        },
+        kwasm_js_object_has_property: function (object_index, property_data, property_length) {
+            let object = kwasm_js_objects[object_index];
+            const property_name = kwasm_decode_string(property_data, property_length);
+            try {
+                return (property_name in Object(object)) ? 1 : 0;
+            } catch (e) {
+                return 0;
+            }
+        },
+        // Passes the object's own enumerable keys to the client.
+        // Each key is written as a little-endian u32 length followed by its UTF-8 bytes.
+        kwasm_js_object_own_keys: function (object_index) {
+            let object = kwasm_js_objects[object_index];
+            const keys = Object.keys(Object(object)).map(key => encoder.encode(key));
+            let length = keys.reduce((total, key) => total + 4 + key.byteLength, 0);
+            let pointer = self.kwasm_exports.kwasm_reserve_space(length);
+            const data_view = new DataView(self.kwasm_memory.buffer, pointer, length);
+            const data = new Uint8Array(self.kwasm_memory.buffer, pointer, length);
+            let offset = 0;
+            for (const key of keys) {
+                data_view.setUint32(offset, key.byteLength, true);
+                data.set(key, offset + 4);
+                offset += 4 + key.byteLength;
+            }
+        },
+        // Returns the exception thrown by the last call that returned KWASM_EXCEPTION_THROWN.
+        kwasm_take_exception: function () {
+            let exception = kwasm_exception;
+            kwasm_exception = 0;
+            return exception;
+        },
+        // Creates a function that calls a Rust closure with `kwasm_call_closure`.
+        kwasm_new_closure: function (closure_data, once) {
+            const f = function (...args) {
+                if (closure_data === 0) {
+                    throw new Error("Called a kwasm closure that was already dropped.");
+                }
+                const data = closure_data;
+                if (once) {
+                    closure_data = 0;
+                }
+
+                // Pass the arguments as little-endian u32 handles.
+                let length = args.length * 4;
+                let pointer = self.kwasm_exports.kwasm_reserve_space(length);
+                const data_view = new DataView(self.kwasm_memory.buffer, pointer, length);
+                args.forEach((arg, i) => data_view.setUint32(i * 4, self.kwasm_new_js_object(arg), true));
+
+                self.kwasm_exports.kwasm_call_closure(data);
+
+                let result = kwasm_closure_result;
+                kwasm_closure_result = undefined;
+                return result;
+            };
+            f.kwasm_drop = function () {
+                closure_data = 0;
+            };
+            return self.kwasm_new_js_object(f);
         },
-        kwasm_new_worker: function (entry_point, stack_pointer, thread_local_storage_pointer) {
-            let worker = new Worker(kwasm_stuff_blob);
+        kwasm_drop_closure: function (function_index) {
+            kwasm_js_objects[function_index].kwasm_drop();
+        },
+        kwasm_set_closure_result: function (object_index) {
+            kwasm_closure_result = kwasm_js_objects[object_index];
+        },
+        kwasm_new_worker: function (entry_point, stack_pointer, thread_local_storage_pointer, name_data, name_length) {
+            const name = kwasm_decode_string(name_data, name_length);
+
+            const main_thread_port = kwasm_new_main_thread_port();
+
+            kwasm_live_children += 1;
+            let worker = new Worker(kwasm_stuff_blob, { name: name });
             worker.postMessage({
                 kwasm_memory: self.kwasm_memory,
                 kwasm_module: self.kwasm_module,
                 entry_point: entry_point,
                 stack_pointer: stack_pointer,
-                thread_local_storage_pointer: thread_local_storage_pointer
-            });
+                thread_local_storage_pointer: thread_local_storage_pointer,
+                main_thread_port: main_thread_port
+            }, [main_thread_port]);
+            worker.onmessage = function (e) {
+                if (e.data.kwasm_worker_exited) {
+                    worker.terminate();
+
+                    // A worker that trapped may have stopped part way through changing the heap
+                    // (for example while holding the allocator's lock) so its memory is leaked.
+                    // If this worker trapped it can't safely free memory either.
+                    if (!e.data.trapped && !kwasm_trapped) {
+                        self.kwasm_exports.kwasm_free_worker(entry_point);
+                    }
+
+                    kwasm_live_children -= 1;
+                    if (kwasm_closing) {
+                        kwasm_close_worker(kwasm_trapped);
+                    }
+                }
+            };
+        },
+        kwasm_new_message_worker: function (entry_point_data, entry_point_length, payload_data, payload_length) {
+            const entry_point = kwasm_decode_string(entry_point_data, entry_point_length);
+            // The worker has its own memory so the payload is copied.
+            const payload = new Uint8Array(self.kwasm_memory.buffer, payload_data, payload_length).slice();
+
+            let worker = new Worker(kwasm_stuff_blob, { name: entry_point });
+            worker.postMessage({
+                kwasm_module: self.kwasm_module,
+                message_worker_entry_point: entry_point,
+                payload: payload
+            }, [payload.buffer]);
+            return self.kwasm_new_js_object(worker);
+        },
+        kwasm_nudge_main_thread: function () {
+            self.kwasm_main_thread_port.postMessage({ kwasm_run_main_thread_queue: true });
+        },
+        kwasm_push_join_state: function (join_state_pointer) {
+            kwasm_join_states.push(join_state_pointer);
+        },
+        kwasm_pop_join_state: function () {
+            kwasm_join_states.pop();
+        },
+        kwasm_exit_worker: function () {
+            // Wait for a new task so nothing is running on this worker's stack when it's freed.
+            setTimeout(function () {
+                kwasm_close_worker(false);
+            }, 0);
         }
     };
 
@@ -140,8 +508,194 @@ function kwasm_stuff() {
         });
     }
 
+    // `TransferableJSObject`s move objects between threads through the main thread,
+    // which has a `MessagePort` connected to every worker.
+    // On the main thread this maps each transfer's id to either the object, if it arrived first,
+    // or to the function that delivers it, if it was requested first.
+    var kwasm_transfers = new Map();
+    // On a worker this maps the ids of requested transfers to the functions that settle their Promise.
+    var kwasm_pending_transfers = new Map();
+
+    function kwasm_is_main_thread() {
+        return self.kwasm_main_thread_port === undefined;
+    }
+
+    // Posts `message` with `object` in its transfer list.
+    // Objects that can't be transferred are copied with structured clone instead.
+    function kwasm_post_with_transfer(port, message, object) {
+        try {
+            port.postMessage(message, [object]);
+        } catch (error) {
+            port.postMessage(message);
+        }
+    }
+
+    // Moves `object` out of the main thread's reach like `kwasm_post_with_transfer` would,
+    // so an object that can't be sent fails now instead of when a worker asks for it.
+    function kwasm_take_object(object) {
+        try {
+            return structuredClone(object, { transfer: [object] });
+        } catch (error) {
+            return structuredClone(object);
+        }
+    }
+
+    // Called on the main thread when a transferred object arrives.
+    function kwasm_main_thread_receive_object(id, object) {
+        const entry = kwasm_transfers.get(id);
+        if (entry === undefined) {
+            kwasm_transfers.set(id, { object: object });
+        } else {
+            kwasm_transfers.delete(id);
+            entry.deliver(object);
+        }
+    }
+
+    // Called on the main thread when a thread wants a transferred object.
+    function kwasm_main_thread_request_object(id, deliver) {
+        const entry = kwasm_transfers.get(id);
+        if (entry === undefined) {
+            kwasm_transfers.set(id, { deliver: deliver });
+        } else {
+            kwasm_transfers.delete(id);
+            deliver(entry.object);
+        }
+    }
+
+    // Handles messages sent to the main thread by a worker.
+    function kwasm_listen_to_worker_port(port) {
+        port.onmessage = function (e) {
+            if (e.data.kwasm_run_main_thread_queue) {
+                // The worker queued a closure, see `kwasm_nudge_main_thread`.
+                try {
+                    self.kwasm_exports.kwasm_run_main_thread_queue();
+                } catch (error) {
+                    // A job that traps leaves the worker that queued it waiting for it.
+                    kwasm_mark_jobs_panicked();
+                    throw error;
+                }
+            } else if (e.data.kwasm_register_port !== undefined) {
+                // A port for a worker spawned by another worker.
+                kwasm_listen_to_worker_port(e.data.kwasm_register_port);
+            } else if (e.data.kwasm_transfer_request !== undefined) {
+                const id = e.data.kwasm_transfer_request;
+                kwasm_main_thread_request_object(id, object => {
+                    try {
+                        kwasm_post_with_transfer(port, { kwasm_transfer_id: id, kwasm_transfer_object: object }, object);
+                    } catch (error) {
+                        // Reject the worker's Promise instead of leaving it waiting.
+                        port.postMessage({ kwasm_transfer_id: id, kwasm_transfer_error: String(error) });
+                    }
+                });
+            } else if (e.data.kwasm_transfer_discard !== undefined) {
+                kwasm_main_thread_request_object(e.data.kwasm_transfer_discard, () => { });
+            } else if (e.data.kwasm_transfer_id !== undefined) {
+                kwasm_main_thread_receive_object(e.data.kwasm_transfer_id, e.data.kwasm_transfer_object);
+            }
+        };
+    }
+
+    // Returns a port for a new worker to send messages to the main thread with.
+    function kwasm_new_main_thread_port() {
+        const channel = new MessageChannel();
+        if (kwasm_is_main_thread()) {
+            kwasm_listen_to_worker_port(channel.port1);
+        } else {
+            self.kwasm_main_thread_port.postMessage({ kwasm_register_port: channel.port1 }, [channel.port1]);
+        }
+        return channel.port2;
+    }
+
+    // Called on a worker with its port to the main thread.
+    function kwasm_listen_to_main_thread_port(port) {
+        self.kwasm_main_thread_port = port;
+        port.onmessage = function (e) {
+            if (e.data.kwasm_transfer_id !== undefined) {
+                const pending = kwasm_pending_transfers.get(e.data.kwasm_transfer_id);
+                kwasm_pending_transfers.delete(e.data.kwasm_transfer_id);
+                if (e.data.kwasm_transfer_error !== undefined) {
+                    pending.reject(new Error(e.data.kwasm_transfer_error));
+                } else {
+                    pending.resolve(e.data.kwasm_transfer_object);
+                }
+            }
+        };
+    }
+
+    // Used by `TransferableJSObject`.
+    self.kwasm_transfer_send = function (id, object) {
+        if (kwasm_is_main_thread()) {
+            kwasm_main_thread_receive_object(id, kwasm_take_object(object));
+        } else {
+            kwasm_post_with_transfer(self.kwasm_main_thread_port, { kwasm_transfer_id: id, kwasm_transfer_object: object }, object);
+        }
+    };
+    self.kwasm_transfer_receive = function (id) {
+        return new Promise((resolve, reject) => {
+            if (kwasm_is_main_thread()) {
+                kwasm_main_thread_request_object(id, resolve);
+            } else {
+                kwasm_pending_transfers.set(id, { resolve: resolve, reject: reject });
+                self.kwasm_main_thread_port.postMessage({ kwasm_transfer_request: id });
+            }
+        });
+    };
+    self.kwasm_transfer_discard = function (id) {
+        if (kwasm_is_main_thread()) {
+            kwasm_main_thread_request_object(id, () => { });
+        } else {
+            self.kwasm_main_thread_port.postMessage({ kwasm_transfer_discard: id });
+        }
+    };
+
+    // Starts a worker spawned with `message_worker::spawn`.
+    // It has its own memory and calls the exported function named by `message_worker_entry_point`.
+    function start_message_worker(data) {
+        // Messages that arrive before Wasm sets a listener are queued. See `message_worker::on_message`.
+        self.kwasm_queued_messages = [];
+        onmessage = function (e) {
+            self.kwasm_queued_messages.push(e.data);
+        };
+
+        self.kwasm_memory = new WebAssembly.Memory({ initial: 32, maximum: 16384 });
+        let imports = {
+            env: Object.assign({ memory: self.kwasm_memory }, kwasm_import_functions)
+        };
+
+        // Fill in any other functions with a placeholder, like a shared memory worker does.
+        WebAssembly.Module.imports(data.kwasm_module).forEach(item => {
+            if (imports[item.module] === undefined) {
+                imports[item.module] = {};
+            }
+            if (item.kind == "function" && !(item.name in imports[item.module])) {
+                imports[item.module][item.name] = function () {
+                    console.log(item.name + "is unimplemented in worker thread.");
+                }
+            }
+        });
+
+        WebAssembly.instantiate(data.kwasm_module, imports).then(instance => {
+            // If this module exports memory use that instead.
+            if (instance.exports.memory) {
+                self.kwasm_memory = instance.exports.memory;
+            }
+            self.kwasm_exports = instance.exports;
+
+            // Pass the payload the same way as other data from the host. See `message_worker::take_payload`.
+            const pointer = self.kwasm_exports.kwasm_reserve_space(data.payload.byteLength);
+            new Uint8Array(self.kwasm_memory.buffer, pointer, data.payload.byteLength).set(data.payload);
+
+            self.kwasm_exports[data.message_worker_entry_point]();
+        });
+    }
+
     // If we're a worker thread we'll use this.
     onmessage = function (e) {
+        if (e.data.message_worker_entry_point !== undefined) {
+            start_message_worker(e.data);
+            return;
+        }
+
         let imports = {
             env: {}
         };
@@ -182,6 +736,7 @@ function kwasm_stuff() {
         }
 
         self.kwasm_memory = e.data.kwasm_memory;
+        kwasm_listen_to_main_thread_port(e.data.main_thread_port);
 
         WebAssembly.instantiate(e.data.kwasm_module, imports).then(results => {
             self.kwasm_exports = results.exports;
@@ -193,7 +748,16 @@ function kwasm_stuff() {
                 self.kwasm_exports.__wasm_init_tls(e.data.thread_local_storage_pointer);
             }
 
-            self.kwasm_exports.kwasm_web_worker_entry_point(e.data.entry_point);
+            try {
+                self.kwasm_exports.kwasm_web_worker_entry_point(e.data.entry_point);
+            } catch (error) {
+                // A panic traps when built with `panic = "abort"`.
+                // Mark the worker, and the pool job it was running if any, as panicked
+                // so that joining them doesn't wait forever.
+                kwasm_mark_jobs_panicked();
+                kwasm_close_worker(true);
+                throw error;
+            }
         });
     }
 
@@ -215,21 +779,114 @@ const kwasm_free_js_object = kwasm.kwasm_free_js_object;
 const kwasm_new_string = kwasm.kwasm_new_string;
 const kwasm_call_js_with_args_raw = kwasm.kwasm_call_js_with_args_raw;
 const kwasm_call_js_with_args = kwasm.kwasm_call_js_with_args;
+const kwasm_call_js_method = kwasm.kwasm_call_js_method;
+const kwasm_call_js_with_typed_args = kwasm.kwasm_call_js_with_typed_args;
+const kwasm_call_js_method_with_typed_args = kwasm.kwasm_call_js_method_with_typed_args;
+const kwasm_construct_js_object = kwasm.kwasm_construct_js_object;
+const kwasm_new_object = kwasm.kwasm_new_object;
+const kwasm_new_array = kwasm.kwasm_new_array;
 const kwasm_js_object_property = kwasm.kwasm_js_object_property;
 const kwasm_get_js_object_value_u32 = kwasm.kwasm_get_js_object_value_u32;
 const kwasm_get_js_object_value_f64 = kwasm.kwasm_get_js_object_value_f64;
+const kwasm_get_js_object_value_bool = kwasm.kwasm_get_js_object_value_bool;
+const kwasm_get_js_object_value_i32 = kwasm.kwasm_get_js_object_value_i32;
+const kwasm_get_js_object_value_i64 = kwasm.kwasm_get_js_object_value_i64;
+const kwasm_js_object_type_of = kwasm.kwasm_js_object_type_of;
+const kwasm_get_js_object_value_string = kwasm.kwasm_get_js_object_value_string;
+const kwasm_js_object_to_string = kwasm.kwasm_js_object_to_string;
+const kwasm_js_object_property_to_string = kwasm.kwasm_js_object_property_to_string;
+const kwasm_take_exception = kwasm.kwasm_take_exception;
+const kwasm_set_js_object_property = kwasm.kwasm_set_js_object_property;
+const kwasm_delete_js_object_property = kwasm.kwasm_delete_js_object_property;
+const kwasm_js_object_has_property = kwasm.kwasm_js_object_has_property;
+const kwasm_js_object_own_keys = kwasm.kwasm_js_object_own_keys;
+const kwasm_new_closure = kwasm.kwasm_new_closure;
+const kwasm_drop_closure = kwasm.kwasm_drop_closure;
+const kwasm_set_closure_result = kwasm.kwasm_set_closure_result;
 
 const kwasm_new_worker = kwasm.kwasm_new_worker;
+const kwasm_new_message_worker = kwasm.kwasm_new_message_worker;
+const kwasm_exit_worker = kwasm.kwasm_exit_worker;
+const kwasm_nudge_main_thread = kwasm.kwasm_nudge_main_thread;
+const kwasm_push_join_state = kwasm.kwasm_push_join_state;
+const kwasm_pop_join_state = kwasm.kwasm_pop_join_state;
 export {
     kwasm_free_js_object as kwasm_free_js_object,
     kwasm_new_string as kwasm_new_string,
     kwasm_call_js_with_args_raw as kwasm_call_js_with_args_raw,
     kwasm_call_js_with_args as kwasm_call_js_with_args,
+    kwasm_call_js_method as kwasm_call_js_method,
+    kwasm_call_js_with_typed_args as kwasm_call_js_with_typed_args,
+    kwasm_call_js_method_with_typed_args as kwasm_call_js_method_with_typed_args,
+    kwasm_construct_js_object as kwasm_construct_js_object,
+    kwasm_new_object as kwasm_new_object,
+    kwasm_new_array as kwasm_new_array,
     kwasm_js_object_property as kwasm_js_object_property,
     kwasm_get_js_object_value_u32 as kwasm_get_js_object_value_u32,
     kwasm_get_js_object_value_f64 as kwasm_get_js_object_value_f64,
-    kwasm_new_worker as kwasm_new_worker
+    kwasm_get_js_object_value_bool as kwasm_get_js_object_value_bool,
+    kwasm_get_js_object_value_i32 as kwasm_get_js_object_value_i32,
+    kwasm_get_js_object_value_i64 as kwasm_get_js_object_value_i64,
+    kwasm_js_object_type_of as kwasm_js_object_type_of,
+    kwasm_get_js_object_value_string as kwasm_get_js_object_value_string,
+    kwasm_js_object_to_string as kwasm_js_object_to_string,
+    kwasm_js_object_property_to_string as kwasm_js_object_property_to_string,
+    kwasm_take_exception as kwasm_take_exception,
+    kwasm_set_js_object_property as kwasm_set_js_object_property,
+    kwasm_delete_js_object_property as kwasm_delete_js_object_property,
+    kwasm_js_object_has_property as kwasm_js_object_has_property,
+    kwasm_js_object_own_keys as kwasm_js_object_own_keys,
+    kwasm_new_closure as kwasm_new_closure,
+    kwasm_drop_closure as kwasm_drop_closure,
+    kwasm_set_closure_result as kwasm_set_closure_result,
+    kwasm_new_worker as kwasm_new_worker,
+    kwasm_new_message_worker as kwasm_new_message_worker,
+    kwasm_exit_worker as kwasm_exit_worker,
+    kwasm_nudge_main_thread as kwasm_nudge_main_thread,
+    kwasm_push_join_state as kwasm_push_join_state,
+    kwasm_pop_join_state as kwasm_pop_join_state
 };
+// Reads from a `kwasm::ring_buffer` in Wasm memory. See `src/ring_buffer.rs` for the memory layout.
+// `memory` is the `WebAssembly.Memory` (or its buffer) and `address` is `RingBufferReader::address()`.
+// Memory that grows must be shared, otherwise growing it detaches the buffer this reads from.
+//
+// This doesn't use anything else in this file, so its source can be loaded into an AudioWorklet.
+export class KwasmRingBufferReader {
+    constructor(memory, address) {
+        const buffer = memory instanceof WebAssembly.Memory ? memory.buffer : memory;
+        // head, tail, capacity, reserved
+        this.header = new Uint32Array(buffer, address, 4);
+        this.capacity = this.header[2];
+        this.data = new Uint8Array(buffer, address + 16, this.capacity);
+    }
+
+    // The number of bytes ready to read.
+    available() {
+        return (Atomics.load(this.header, 1) - Atomics.load(this.header, 0)) >>> 0;
+    }
+
+    // Reads as many whole elements as are ready into the typed array `target`
+    // and returns how many were read.
+    read(target) {
+        const element_size = target.BYTES_PER_ELEMENT || 1;
+        const bytes = new Uint8Array(target.buffer, target.byteOffset, target.byteLength);
+
+        const head = Atomics.load(this.header, 0);
+        const tail = Atomics.load(this.header, 1);
+        let count = Math.min((tail - head) >>> 0, bytes.length);
+        count -= count % element_size;
+
+        // The data may wrap around the end of the buffer.
+        const start = head & (this.capacity - 1);
+        const first = Math.min(count, this.capacity - start);
+        bytes.set(this.data.subarray(start, start + first));
+        bytes.set(this.data.subarray(0, count - first), first);
+
+        Atomics.store(this.header, 0, (head + count) >>> 0);
+        return count / element_size;
+    }
+}
+
 export function kwasm_initialize_wasmbindgen(module, memory) {
     self.kwasm_module = module;
     self.kwasm_memory = memory;
@@ -0,0 +1,83 @@
+use crate::*;
+
+thread_local! {
+    static ADD_EVENT_LISTENER: JSObjectFromString = JSObjectFromString::new(
+        r#"
+        function add_event_listener(target, event, listener, capture, once, passive) {
+            target.addEventListener(event, listener, { capture, once, passive });
+            return () => target.removeEventListener(event, listener, { capture });
+        };
+        add_event_listener
+        "#,
+    );
+}
+
+/// Options passed to `addEventListener`.
+/// See here: https://developer.mozilla.org/en-US/docs/Web/API/EventTarget/addEventListener
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EventListenerOptions {
+    /// Receive the event during the capture phase instead of the bubbling phase.
+    pub capture: bool,
+    /// Remove the listener after it receives one event.
+    pub once: bool,
+    /// Promise not to call `preventDefault`, which lets the browser scroll without waiting.
+    pub passive: bool,
+}
+
+/// Removes an event listener when dropped.
+pub struct EventListenerGuard {
+    remove: JSObjectDynamic,
+    _listener: JSClosure,
+}
+
+impl Drop for EventListenerGuard {
+    fn drop(&mut self) {
+        let _ = self.remove.call(&JSObject::NULL);
+    }
+}
+
+impl JSObject {
+    /// Calls `listener` with the event object each time this receives the event.
+    /// The listener is removed when the returned guard is dropped.
+    pub fn add_event_listener(
+        &self,
+        event: &str,
+        listener: impl FnMut(JSObjectDynamic) + 'static,
+    ) -> Result<EventListenerGuard, JSError> {
+        self.add_event_listener_with_options(event, EventListenerOptions::default(), listener)
+    }
+
+    /// Like `add_event_listener` but with options for `capture`, `once`, and `passive`.
+    pub fn add_event_listener_with_options(
+        &self,
+        event: &str,
+        options: EventListenerOptions,
+        mut listener: impl FnMut(JSObjectDynamic) + 'static,
+    ) -> Result<EventListenerGuard, JSError> {
+        let listener = JSClosure::new(move |args| {
+            listener(args[0].clone());
+            None
+        });
+
+        let remove = ADD_EVENT_LISTENER
+            .with(|f| {
+                f.call_typed(
+                    &JSObject::NULL,
+                    &[
+                        self.into_js_arg(),
+                        event.into_js_arg(),
+                        (&*listener).into_js_arg(),
+                        options.capture.into_js_arg(),
+                        options.once.into_js_arg(),
+                        options.passive.into_js_arg(),
+                    ],
+                )
+            })?
+            .unwrap();
+
+        Ok(EventListenerGuard {
+            remove,
+            _listener: listener,
+        })
+    }
+}
@@ -0,0 +1,299 @@
+//! Executors driven by the Javascript event loop.
+//!
+//! `spawn_local` runs a future on the current thread.
+//! Each thread, including Web Workers started with `web_worker::spawn`, has its own task queue.
+//! No event loop needs to be run: tasks are polled from a microtask scheduled with
+//! `queueMicrotask` whenever they're woken.
+//!
+//! `spawn` runs a `Send` future on a pool of Web Workers created with `create_workers`.
+
+use crate::*;
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{Context, Wake, Waker};
+
+#[cfg(target_feature = "atomics")]
+mod multi_threaded;
+#[cfg(target_feature = "atomics")]
+pub use multi_threaded::*;
+
+struct Task {
+    // Taken out while the task is polled.
+    future: RefCell<Option<Pin<Box<dyn Future<Output = ()>>>>>,
+    queued: Cell<bool>,
+    waker: Waker,
+}
+
+/// Tasks owned by one thread that were woken by other threads.
+struct RemoteWakes {
+    // Changed whenever a task is added so the owning thread can wait on it.
+    state: AtomicI32,
+    tasks: Mutex<Vec<usize>>,
+}
+
+/// Wakes a task owned by a specific thread.
+/// If it's woken from another thread the task is routed back to its owner.
+struct TaskWaker {
+    task_id: usize,
+    owner: Arc<RemoteWakes>,
+}
+
+thread_local! {
+    // Tasks are stored by id so `Send` wakers can refer to them from any thread.
+    static TASKS: RefCell<Vec<Option<Rc<Task>>>> = const { RefCell::new(Vec::new()) };
+    static FREE_TASK_IDS: RefCell<Vec<usize>> = const { RefCell::new(Vec::new()) };
+    static TASK_QUEUE: RefCell<VecDeque<usize>> = const { RefCell::new(VecDeque::new()) };
+    // Tasks spawned with `spawn_local` that haven't completed.
+    static TASK_COUNT: Cell<usize> = const { Cell::new(0) };
+    static REMOTE_WAKES: Arc<RemoteWakes> = Arc::new(RemoteWakes {
+        state: AtomicI32::new(0),
+        tasks: Mutex::new(Vec::new()),
+    });
+    static QUEUE_MICROTASK: JSObjectFromString = JSObjectFromString::new("queueMicrotask");
+    static RUN_TASKS: JSClosure = JSClosure::new(|_| {
+        run_tasks();
+        None
+    });
+}
+
+#[cfg(target_feature = "atomics")]
+thread_local! {
+    static LISTENING_FOR_REMOTE_WAKES: Cell<bool> = const { Cell::new(false) };
+    static WAIT_ASYNC: JSObjectFromString = JSObjectFromString::new(
+        r#"
+        function wait_async(address, value) {
+            const index = address / 4;
+            if (Atomics.waitAsync) {
+                const result = Atomics.waitAsync(new Int32Array(self.kwasm_memory.buffer), index, value);
+                return result.async ? result.value : Promise.resolve();
+            }
+
+            // Browsers without `waitAsync` poll instead.
+            return new Promise(resolve => {
+                function check() {
+                    if (Atomics.load(new Int32Array(self.kwasm_memory.buffer), index) !== value) {
+                        resolve();
+                    } else {
+                        setTimeout(check, 1);
+                    }
+                }
+                check();
+            });
+        };
+        wait_async
+        "#,
+    );
+}
+
+/// Runs a future on the current thread.
+/// The future is first polled from a microtask, and again each time it is woken.
+pub fn spawn_local(future: impl Future<Output = ()> + 'static) {
+    #[cfg(target_feature = "atomics")]
+    if !LISTENING_FOR_REMOTE_WAKES.with(|l| l.replace(true)) {
+        spawn_local_inner(listen_for_remote_wakes());
+    }
+    TASK_COUNT.with(|c| c.set(c.get() + 1));
+    spawn_local_inner(future);
+}
+
+fn spawn_local_inner(future: impl Future<Output = ()> + 'static) {
+    let task_id = FREE_TASK_IDS
+        .with(|f| f.borrow_mut().pop())
+        .unwrap_or_else(|| {
+            TASKS.with(|tasks| {
+                let mut tasks = tasks.borrow_mut();
+                tasks.push(None);
+                tasks.len() - 1
+            })
+        });
+
+    let waker = Waker::from(Arc::new(TaskWaker {
+        task_id,
+        owner: REMOTE_WAKES.with(|r| r.clone()),
+    }));
+    let task = Rc::new(Task {
+        future: RefCell::new(Some(Box::pin(future))),
+        queued: Cell::new(false),
+        waker,
+    });
+    TASKS.with(|tasks| tasks.borrow_mut()[task_id] = Some(task));
+    schedule(task_id);
+}
+
+/// Runs a `Send` future on the worker pool created by `create_workers`.
+/// Without any workers the future is run on the current thread.
+pub fn spawn(future: impl Future<Output = ()> + Send + 'static) {
+    #[cfg(target_feature = "atomics")]
+    if let Some(pool) = multi_threaded::pool() {
+        pool.spawn(future);
+        return;
+    }
+    spawn_local(future);
+}
+
+fn get_task(task_id: usize) -> Option<Rc<Task>> {
+    TASKS.with(|tasks| tasks.borrow().get(task_id).cloned().flatten())
+}
+
+fn schedule(task_id: usize) {
+    // The task may have already completed.
+    let task = match get_task(task_id) {
+        Some(task) => task,
+        None => return,
+    };
+    if task.queued.replace(true) {
+        return;
+    }
+
+    let was_empty = TASK_QUEUE.with(|queue| {
+        let mut queue = queue.borrow_mut();
+        queue.push_back(task_id);
+        queue.len() == 1
+    });
+
+    // The queue is drained in one microtask, so only schedule one if the queue was empty.
+    if was_empty {
+        RUN_TASKS.with(|run_tasks| {
+            QUEUE_MICROTASK
+                .with(|f| f.call_1_arg(&JSObject::NULL, run_tasks))
+                .unwrap();
+        });
+    }
+}
+
+fn run_tasks() {
+    loop {
+        // Don't hold the borrow while polling, the task may wake itself.
+        let task_id = match TASK_QUEUE.with(|queue| queue.borrow_mut().pop_front()) {
+            Some(task_id) => task_id,
+            None => {
+                #[cfg(target_feature = "atomics")]
+                web_worker::exit_if_finished();
+                return;
+            }
+        };
+        let task = match get_task(task_id) {
+            Some(task) => task,
+            None => continue,
+        };
+        task.queued.set(false);
+
+        let future = task.future.borrow_mut().take();
+        if let Some(mut future) = future {
+            let mut cx = Context::from_waker(&task.waker);
+            if future.as_mut().poll(&mut cx).is_pending() {
+                *task.future.borrow_mut() = Some(future);
+            } else {
+                TASKS.with(|tasks| tasks.borrow_mut()[task_id] = None);
+                FREE_TASK_IDS.with(|f| f.borrow_mut().push(task_id));
+                TASK_COUNT.with(|c| c.set(c.get() - 1));
+            }
+        }
+
+        #[cfg(target_feature = "atomics")]
+        web_worker::check_stack_overflow_sampled();
+    }
+}
+
+/// The number of tasks spawned on this thread with `spawn_local` that haven't completed.
+#[cfg(target_feature = "atomics")]
+pub(crate) fn local_task_count() -> usize {
+    TASK_COUNT.with(|c| c.get())
+}
+
+/// Drops every task owned by this thread without completing them.
+/// Used when a worker exits.
+#[cfg(target_feature = "atomics")]
+pub(crate) fn drop_local_tasks() {
+    // Take everything out first so futures dropped here can't see a borrowed executor.
+    let tasks = TASKS.with(|tasks| std::mem::take(&mut *tasks.borrow_mut()));
+    TASK_QUEUE.with(|queue| queue.borrow_mut().clear());
+    FREE_TASK_IDS.with(|f| f.borrow_mut().clear());
+    TASK_COUNT.with(|c| c.set(0));
+    LISTENING_FOR_REMOTE_WAKES.with(|l| l.set(false));
+    drop(tasks);
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref()
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        let on_owner_thread = REMOTE_WAKES
+            .try_with(|r| Arc::ptr_eq(r, &self.owner))
+            .unwrap_or(false);
+
+        if on_owner_thread {
+            schedule(self.task_id);
+        } else {
+            lock(&self.owner.tasks).push(self.task_id);
+            notify(&self.owner.state);
+        }
+    }
+}
+
+/// Changes the value at `address` and wakes one thread waiting on it.
+pub(crate) fn notify(address: &AtomicI32) {
+    address.fetch_add(1, Ordering::SeqCst);
+    #[cfg(target_feature = "atomics")]
+    unsafe {
+        core::arch::wasm32::memory_atomic_notify(address as *const AtomicI32 as *mut i32, 1);
+    }
+}
+
+/// Locks `mutex`, spinning on the main thread where `Mutex::lock` traps if it has to wait.
+pub(crate) fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    #[cfg(target_feature = "atomics")]
+    if crate::web_worker::is_worker() {
+        return mutex.lock().unwrap();
+    }
+    loop {
+        match mutex.try_lock() {
+            Ok(guard) => return guard,
+            Err(std::sync::TryLockError::Poisoned(error)) => panic!("{}", error),
+            Err(std::sync::TryLockError::WouldBlock) => std::hint::spin_loop(),
+        }
+    }
+}
+
+/// Completes when the value at `address` is no longer `value`.
+/// This uses `Atomics.waitAsync` so it can be used on the main thread, and it keeps
+/// the Javascript event loop running while it waits.
+#[cfg(target_feature = "atomics")]
+pub(crate) fn wait_async(address: &AtomicI32, value: i32) -> JSFuture {
+    let promise = WAIT_ASYNC
+        .with(|f| {
+            f.call_typed(
+                &JSObject::NULL,
+                &[
+                    (address as *const AtomicI32 as u32).into_js_arg(),
+                    value.into_js_arg(),
+                ],
+            )
+        })
+        .unwrap()
+        .unwrap();
+    JSFuture::new(&promise)
+}
+
+/// Schedules tasks owned by this thread that were woken by other threads.
+/// Runs as a task on each thread that spawns tasks.
+#[cfg(target_feature = "atomics")]
+async fn listen_for_remote_wakes() {
+    let remote_wakes = REMOTE_WAKES.with(|r| r.clone());
+    loop {
+        // Read the state first so a wake that happens after draining the list isn't missed.
+        let state = remote_wakes.state.load(Ordering::SeqCst);
+        let task_ids = std::mem::take(&mut *lock(&remote_wakes.tasks));
+        for task_id in task_ids {
+            schedule(task_id);
+        }
+        let _ = wait_async(&remote_wakes.state, state).await;
+    }
+}
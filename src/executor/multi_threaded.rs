@@ -0,0 +1,147 @@
+use super::*;
+use std::sync::atomic::AtomicBool;
+use std::sync::OnceLock;
+
+/// How many tasks a worker runs before letting its Javascript event loop run.
+const TASKS_PER_BATCH: usize = 64;
+
+static POOL: OnceLock<Pool> = OnceLock::new();
+
+thread_local! {
+    // The index of this thread's queue if this thread is one of the pool's workers.
+    static WORKER_INDEX: Cell<Option<usize>> = const { Cell::new(None) };
+    static YIELD: JSObjectFromString =
+        JSObjectFromString::new("function yield_now() { return new Promise(resolve => setTimeout(resolve, 0)) }; yield_now");
+}
+
+type SendFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+struct SendTask {
+    // Locked for the duration of a poll, so a task woken while it's being polled
+    // is polled again afterwards instead of being lost.
+    future: Mutex<Option<SendFuture>>,
+    queued: AtomicBool,
+}
+
+pub(super) struct Pool {
+    // Changed whenever a task is queued so idle workers can wait on it.
+    state: AtomicI32,
+    // Tasks spawned by threads that aren't workers.
+    injector: Mutex<VecDeque<Arc<SendTask>>>,
+    // Each worker's own tasks. Idle workers steal from the back of other workers' queues.
+    queues: Vec<Mutex<VecDeque<Arc<SendTask>>>>,
+}
+
+pub(super) fn pool() -> Option<&'static Pool> {
+    POOL.get()
+}
+
+/// Starts `count` Web Workers that run futures passed to `spawn`.
+/// `available_threads()` is a good choice for `count`.
+///
+/// Each worker also runs its own `spawn_local` tasks, so futures that aren't `Send`
+/// stay on the thread that spawned them.
+///
+/// A `count` of 0 creates no workers, so `spawn` keeps running futures on the current thread.
+///
+/// Panics if workers have already been created.
+pub fn create_workers(count: u32) {
+    if count == 0 {
+        return;
+    }
+    let pool = Pool {
+        state: AtomicI32::new(0),
+        injector: Mutex::new(VecDeque::new()),
+        queues: (0..count).map(|_| Mutex::new(VecDeque::new())).collect(),
+    };
+    if POOL.set(pool).is_err() {
+        panic!("Workers have already been created");
+    }
+
+    for index in 0..count as usize {
+        let name = format!("kwasm executor {}", index);
+        web_worker::Builder::new().name(&name).spawn(move || {
+            WORKER_INDEX.with(|w| w.set(Some(index)));
+            spawn_local(run_worker(index));
+        });
+    }
+}
+
+impl Pool {
+    pub(super) fn spawn(&'static self, future: impl Future<Output = ()> + Send + 'static) {
+        let task = Arc::new(SendTask {
+            future: Mutex::new(Some(Box::pin(future))),
+            queued: AtomicBool::new(true),
+        });
+        self.push(task);
+    }
+
+    fn push(&self, task: Arc<SendTask>) {
+        match WORKER_INDEX.try_with(|w| w.get()).ok().flatten() {
+            Some(index) => lock(&self.queues[index]).push_back(task),
+            None => lock(&self.injector).push_back(task),
+        }
+        notify(&self.state);
+    }
+
+    fn find_task(&self, index: usize) -> Option<Arc<SendTask>> {
+        if let Some(task) = lock(&self.queues[index]).pop_front() {
+            return Some(task);
+        }
+        if let Some(task) = lock(&self.injector).pop_front() {
+            return Some(task);
+        }
+        let count = self.queues.len();
+        (1..count).find_map(|offset| lock(&self.queues[(index + offset) % count]).pop_back())
+    }
+}
+
+impl SendTask {
+    fn run(self: Arc<Self>) {
+        let mut future = lock(&self.future);
+        self.queued.store(false, Ordering::SeqCst);
+        if let Some(f) = future.as_mut() {
+            let waker = Waker::from(self.clone());
+            let mut cx = Context::from_waker(&waker);
+            if f.as_mut().poll(&mut cx).is_ready() {
+                *future = None;
+            }
+        }
+    }
+}
+
+impl Wake for SendTask {
+    fn wake(self: Arc<Self>) {
+        if !self.queued.swap(true, Ordering::SeqCst) {
+            if let Some(pool) = pool() {
+                pool.push(self);
+            }
+        }
+    }
+}
+
+/// Runs on each worker as a local task so the worker's event loop keeps running
+/// while it waits for more work.
+async fn run_worker(index: usize) {
+    let pool = pool().unwrap();
+    loop {
+        // Read the state first so a task queued after the queues are checked isn't missed.
+        let state = pool.state.load(Ordering::SeqCst);
+
+        let mut tasks_run = 0;
+        while tasks_run < TASKS_PER_BATCH {
+            match pool.find_task(index) {
+                Some(task) => task.run(),
+                None => break,
+            }
+            tasks_run += 1;
+        }
+
+        if tasks_run == TASKS_PER_BATCH {
+            let promise = YIELD.with(|f| f.call(&JSObject::NULL)).unwrap().unwrap();
+            let _ = JSFuture::new(&promise).await;
+        } else if tasks_run == 0 {
+            let _ = wait_async(&pool.state, state).await;
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use crate::*;
+use std::marker::PhantomData;
+
+const TAG_HANDLE: u32 = 0;
+const TAG_F64: u32 = 1;
+const TAG_I32: u32 = 2;
+const TAG_U32: u32 = 3;
+const TAG_STRING: u32 = 4;
+
+/// An argument passed to Javascript without allocating a handle for it.
+///
+/// Each argument is encoded as 16 bytes that the host decodes:
+/// a u32 tag, a u32 payload, then a u64 payload.
+/// * Handles store the handle index in the u32 payload.
+/// * f64s store their bits in the u64 payload.
+/// * i32s and u32s are stored in the u32 payload.
+/// * Strings store a pointer to their UTF-8 bytes in the u32 payload and their length in the u64 payload.
+///
+/// Booleans, `undefined`, and `null` are passed as their reserved handles.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct JSArg<'a> {
+    tag: u32,
+    value0: u32,
+    value1: u64,
+    phantom: PhantomData<&'a str>,
+}
+
+impl<'a> JSArg<'a> {
+    fn new(tag: u32, value0: u32, value1: u64) -> Self {
+        Self {
+            tag,
+            value0,
+            value1,
+            phantom: PhantomData,
+        }
+    }
+}
+
+/// Values that can be passed to Javascript as a `JSArg`.
+pub trait IntoJSArg<'a> {
+    fn into_js_arg(self) -> JSArg<'a>;
+}
+
+impl<'a> IntoJSArg<'a> for &'a JSObject {
+    fn into_js_arg(self) -> JSArg<'a> {
+        JSArg::new(TAG_HANDLE, self.index(), 0)
+    }
+}
+
+impl<'a> IntoJSArg<'a> for &'a JSObjectDynamic {
+    fn into_js_arg(self) -> JSArg<'a> {
+        JSArg::new(TAG_HANDLE, self.index(), 0)
+    }
+}
+
+impl<'a> IntoJSArg<'a> for &'a str {
+    fn into_js_arg(self) -> JSArg<'a> {
+        JSArg::new(TAG_STRING, self.as_ptr() as u32, self.len() as u64)
+    }
+}
+
+impl<'a> IntoJSArg<'a> for &'a String {
+    fn into_js_arg(self) -> JSArg<'a> {
+        self.as_str().into_js_arg()
+    }
+}
+
+impl<'a> IntoJSArg<'a> for f64 {
+    fn into_js_arg(self) -> JSArg<'a> {
+        JSArg::new(TAG_F64, 0, self.to_bits())
+    }
+}
+
+impl<'a> IntoJSArg<'a> for f32 {
+    fn into_js_arg(self) -> JSArg<'a> {
+        (self as f64).into_js_arg()
+    }
+}
+
+impl<'a> IntoJSArg<'a> for i32 {
+    fn into_js_arg(self) -> JSArg<'a> {
+        JSArg::new(TAG_I32, self as u32, 0)
+    }
+}
+
+impl<'a> IntoJSArg<'a> for u32 {
+    fn into_js_arg(self) -> JSArg<'a> {
+        JSArg::new(TAG_U32, self, 0)
+    }
+}
+
+impl<'a> IntoJSArg<'a> for bool {
+    fn into_js_arg(self) -> JSArg<'a> {
+        if self {
+            JSArg::new(TAG_HANDLE, JS_TRUE.index(), 0)
+        } else {
+            JSArg::new(TAG_HANDLE, JS_FALSE.index(), 0)
+        }
+    }
+}
+
+/// `None` is passed as `undefined`.
+impl<'a, T: IntoJSArg<'a>> IntoJSArg<'a> for Option<T> {
+    fn into_js_arg(self) -> JSArg<'a> {
+        match self {
+            Some(value) => value.into_js_arg(),
+            None => JSArg::new(TAG_HANDLE, JS_UNDEFINED.index(), 0),
+        }
+    }
+}
@@ -0,0 +1,106 @@
+use crate::*;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+type ClosureFn = dyn FnMut(&[JSObjectDynamic]) -> Option<JSObjectDynamic>;
+
+struct ClosureData {
+    closure: RefCell<Box<ClosureFn>>,
+    once: bool,
+}
+
+/// A Rust closure that can be called from Javascript as a function.
+///
+/// The closure is freed when the `JSClosure` is dropped,
+/// or when the call returns if it's dropped from within the closure.
+/// After that calling the Javascript function throws an exception.
+pub struct JSClosure {
+    js_function: JSObjectDynamic,
+    // The reference held by the Javascript function.
+    closure_data: *const ClosureData,
+}
+
+impl JSClosure {
+    /// Creates a Javascript function that calls `f` with the function's arguments.
+    /// If `f` returns `None` the function returns `undefined`.
+    pub fn new(f: impl FnMut(&[JSObjectDynamic]) -> Option<JSObjectDynamic> + 'static) -> Self {
+        let closure_data = new_closure_data(Box::new(f), false);
+        let js_function = unsafe { JSObject::new_raw(kwasm_new_closure(closure_data as u32, 0)) };
+        Self {
+            js_function,
+            closure_data,
+        }
+    }
+
+    /// Creates a Javascript function that can only be called once.
+    /// The closure frees itself after it is called, so there's no `JSClosure` to hold on to.
+    /// If the function is never called the closure is leaked.
+    pub fn once(
+        f: impl FnOnce(&[JSObjectDynamic]) -> Option<JSObjectDynamic> + 'static,
+    ) -> JSObjectDynamic {
+        let mut f = Some(f);
+        let closure_data = new_closure_data(
+            Box::new(move |args: &[JSObjectDynamic]| (f.take().unwrap())(args)),
+            true,
+        );
+        unsafe { JSObject::new_raw(kwasm_new_closure(closure_data as u32, 1)) }
+    }
+}
+
+fn new_closure_data(closure: Box<ClosureFn>, once: bool) -> *const ClosureData {
+    Rc::into_raw(Rc::new(ClosureData {
+        closure: RefCell::new(closure),
+        once,
+    }))
+}
+
+impl Deref for JSClosure {
+    type Target = JSObject;
+    fn deref(&self) -> &Self::Target {
+        &self.js_function
+    }
+}
+
+impl Drop for JSClosure {
+    fn drop(&mut self) {
+        unsafe {
+            kwasm_drop_closure(self.js_function.index());
+            drop(Rc::from_raw(self.closure_data));
+        }
+    }
+}
+
+/// Called by the host when a function created by `JSClosure` is called.
+/// The function's arguments are passed as u32 handles in `DATA_FROM_HOST`.
+#[no_mangle]
+extern "C" fn kwasm_call_closure(closure_data: u32) {
+    let args: Vec<JSObjectDynamic> = DATA_FROM_HOST.with(|d| {
+        d.take()
+            .chunks_exact(4)
+            .map(|c| unsafe { JSObject::new_raw(u32::from_le_bytes([c[0], c[1], c[2], c[3]])) })
+            .collect()
+    });
+
+    // Hold a reference for the duration of the call so the closure isn't freed
+    // if its `JSClosure` is dropped from within it.
+    // A once closure's Javascript function can't call it again, so its reference is taken instead.
+    let closure_data = closure_data as *const ClosureData;
+    let closure_data = unsafe {
+        if !(*closure_data).once {
+            Rc::increment_strong_count(closure_data);
+        }
+        Rc::from_raw(closure_data)
+    };
+    let result = {
+        let mut closure = closure_data
+            .closure
+            .try_borrow_mut()
+            .expect("A JSClosure cannot be called from within itself");
+        (closure)(&args)
+    };
+
+    // The host holds on to the result so it can be freed here.
+    if let Some(result) = result {
+        unsafe { kwasm_set_closure_result(result.index()) }
+    }
+}
@@ -0,0 +1,58 @@
+use crate::*;
+use std::fmt;
+
+/// An exception thrown by Javascript code that was called from Rust.
+///
+/// The exception is caught on the Javascript side so it can't unwind through Wasm.
+#[derive(Debug, Clone)]
+pub struct JSError {
+    /// The thrown value itself.
+    pub exception: JSObjectDynamic,
+    /// The exception's `name`, like "TypeError".
+    /// Empty if the thrown value has no `name`.
+    pub name: String,
+    /// The exception's `message`.
+    /// If something other than an `Error` was thrown this is the thrown value as a string.
+    pub message: String,
+    /// The exception's `stack`, if the browser provides one.
+    pub stack: String,
+}
+
+impl JSError {
+    pub(crate) fn from_exception(exception: JSObjectDynamic) -> Self {
+        let name = property_to_string(&exception, "name").unwrap_or_default();
+        let stack = property_to_string(&exception, "stack").unwrap_or_default();
+        let message =
+            property_to_string(&exception, "message").unwrap_or_else(|| exception.to_string());
+
+        Self {
+            exception,
+            name,
+            message,
+            stack,
+        }
+    }
+}
+
+fn property_to_string(object: &JSObject, property: &str) -> Option<String> {
+    let found = unsafe {
+        kwasm_js_object_property_to_string(object.index(), property.as_ptr(), property.len() as u32)
+    };
+    if found == 0 {
+        None
+    } else {
+        Some(get_string_from_host())
+    }
+}
+
+impl fmt::Display for JSError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.name.is_empty() {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "{}: {}", self.name, self.message)
+        }
+    }
+}
+
+impl std::error::Error for JSError {}
@@ -0,0 +1,123 @@
+use crate::*;
+use std::cell::RefCell;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll, Waker};
+
+thread_local! {
+    static PROMISE_THEN: JSObjectFromString = JSObjectFromString::new(
+        r#"
+        function promise_then(promise, callback) {
+            Promise.resolve(promise).then(
+                value => callback(true, value),
+                error => callback(false, error)
+            );
+        };
+        promise_then
+        "#,
+    );
+    static NEW_PROMISE: JSObjectFromString = JSObjectFromString::new(
+        r#"
+        function new_promise() {
+            let result = {};
+            result.promise = new Promise((resolve, reject) => {
+                result.resolve = resolve;
+                result.reject = reject;
+            });
+            return result;
+        };
+        new_promise
+        "#,
+    );
+}
+
+struct Inner {
+    result: Option<Result<JSObjectDynamic, JSError>>,
+    waker: Option<Waker>,
+}
+
+/// A future that completes when a Javascript Promise settles.
+///
+/// Resolves to the Promise's value, or a `JSError` if the Promise is rejected.
+/// Values that aren't a Promise are treated like an already resolved Promise.
+pub struct JSFuture {
+    // Shared with the callback passed to the Promise.
+    inner: Rc<RefCell<Inner>>,
+}
+
+impl JSFuture {
+    pub fn new(promise: &JSObject) -> Self {
+        let inner = Rc::new(RefCell::new(Inner {
+            result: None,
+            waker: None,
+        }));
+
+        // Exactly one of the Promise's callbacks is called so a once closure won't leak
+        // unless the Promise never settles.
+        let callback_inner = inner.clone();
+        let callback = JSClosure::once(move |args| {
+            let result = if args[0].get_value_bool() {
+                Ok(args[1].clone())
+            } else {
+                Err(JSError::from_exception(args[1].clone()))
+            };
+
+            let waker = {
+                let mut inner = callback_inner.borrow_mut();
+                inner.result = Some(result);
+                inner.waker.take()
+            };
+
+            // Drop the borrow before waking in case the task is polled immediately.
+            if let Some(waker) = waker {
+                waker.wake();
+            }
+            None
+        });
+
+        PROMISE_THEN
+            .with(|f| f.call_2_arg(&JSObject::NULL, promise, &callback))
+            .unwrap();
+
+        Self { inner }
+    }
+}
+
+impl Future for JSFuture {
+    type Output = Result<JSObjectDynamic, JSError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let mut inner = self.inner.borrow_mut();
+        if let Some(result) = inner.result.take() {
+            Poll::Ready(result)
+        } else {
+            inner.waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+/// Returns a Javascript Promise that settles when `future` completes.
+///
+/// The future is spawned on the current thread.
+/// `Ok` resolves the Promise and `Err` rejects it.
+pub fn future_to_promise(
+    future: impl Future<Output = Result<JSObjectDynamic, JSObjectDynamic>> + 'static,
+) -> JSObjectDynamic {
+    let new_promise = NEW_PROMISE
+        .with(|f| f.call(&JSObject::NULL))
+        .unwrap()
+        .unwrap();
+    let resolve = new_promise.get_property("resolve");
+    let reject = new_promise.get_property("reject");
+
+    spawn_local(async move {
+        let _ = match future.await {
+            Ok(value) => resolve.call_1_arg(&JSObject::NULL, &value),
+            Err(error) => reject.call_1_arg(&JSObject::NULL, &error),
+        };
+    });
+
+    new_promise.get_property("promise")
+}
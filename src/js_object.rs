@@ -1,4 +1,5 @@
-use std::{borrow::Borrow, cell::Cell, ffi::c_void, ops::Deref, rc::Rc};
+use crate::{JSArg, JSError};
+use std::{borrow::Borrow, cell::Cell, ffi::c_void, fmt, ops::Deref, rc::Rc};
 
 #[cfg(feature = "wasm_bindgen_support")]
 use wasm_bindgen::prelude::*;
@@ -13,6 +14,35 @@ extern "C" {
     pub(crate) fn kwasm_js_object_property(function_object: u32, property: u32) -> u32;
     pub(crate) fn kwasm_get_js_object_value_u32(object: u32) -> u32;
     pub(crate) fn kwasm_get_js_object_value_f64(object: u32) -> f64;
+    pub(crate) fn kwasm_get_js_object_value_bool(object: u32) -> u32;
+    pub(crate) fn kwasm_get_js_object_value_i32(object: u32) -> i32;
+    pub(crate) fn kwasm_get_js_object_value_i64(object: u32) -> i64;
+    pub(crate) fn kwasm_js_object_type_of(object: u32) -> u32;
+    pub(crate) fn kwasm_js_object_to_string(object: u32);
+    pub(crate) fn kwasm_get_js_object_value_string(object: u32) -> u32;
+    pub(crate) fn kwasm_js_object_property_to_string(
+        object: u32,
+        property_data: *const u8,
+        property_length: u32,
+    ) -> u32;
+    pub(crate) fn kwasm_take_exception() -> u32;
+    pub(crate) fn kwasm_set_js_object_property(
+        object: u32,
+        property_data: *const u8,
+        property_length: u32,
+        value: u32,
+    ) -> u32;
+    pub(crate) fn kwasm_delete_js_object_property(
+        object: u32,
+        property_data: *const u8,
+        property_length: u32,
+    ) -> u32;
+    pub(crate) fn kwasm_js_object_has_property(
+        object: u32,
+        property_data: *const u8,
+        property_length: u32,
+    ) -> u32;
+    pub(crate) fn kwasm_js_object_own_keys(object: u32);
 
     pub(crate) fn kwasm_call_js_with_args(
         function_object: u32,
@@ -26,12 +56,59 @@ extern "C" {
         args_data: *const c_void,
         data_length: u32,
     ) -> u32;
+    pub(crate) fn kwasm_call_js_method(
+        object: u32,
+        method_data: *const u8,
+        method_length: u32,
+        args_data: *const c_void,
+        data_length: u32,
+    ) -> u32;
+    pub(crate) fn kwasm_call_js_with_typed_args(
+        function_object: u32,
+        this: u32,
+        args_data: *const c_void,
+        args_length: u32,
+    ) -> u32;
+    pub(crate) fn kwasm_call_js_method_with_typed_args(
+        object: u32,
+        method_data: *const u8,
+        method_length: u32,
+        args_data: *const c_void,
+        args_length: u32,
+    ) -> u32;
+    pub(crate) fn kwasm_construct_js_object(
+        constructor: u32,
+        args_data: *const c_void,
+        data_length: u32,
+    ) -> u32;
+    pub(crate) fn kwasm_new_object() -> u32;
+    pub(crate) fn kwasm_new_array() -> u32;
+    pub(crate) fn kwasm_new_closure(closure_data: u32, once: u32) -> u32;
+    pub(crate) fn kwasm_drop_closure(function_object: u32);
+    pub(crate) fn kwasm_set_closure_result(object: u32);
+    #[cfg(not(target_feature = "atomics"))]
+    pub(crate) fn kwasm_new_message_worker(
+        entry_point_data: *const u8,
+        entry_point_length: u32,
+        payload_data: *const u8,
+        payload_length: u32,
+    ) -> u32;
     #[cfg(target_feature = "atomics")]
     pub(crate) fn kwasm_new_worker(
         entry_point: u32,
         stack_pointer: u32,
         thread_local_storage_pointer: u32,
+        name_data: *const u8,
+        name_length: u32,
     );
+    #[cfg(target_feature = "atomics")]
+    pub(crate) fn kwasm_exit_worker();
+    #[cfg(target_feature = "atomics")]
+    pub(crate) fn kwasm_nudge_main_thread();
+    #[cfg(target_feature = "atomics")]
+    pub(crate) fn kwasm_push_join_state(join_state_pointer: u32);
+    #[cfg(target_feature = "atomics")]
+    pub(crate) fn kwasm_pop_join_state();
 }
 
 fn kwasm_call_js_with_args0(function_object: u32, this: u32, args: &[u32]) -> u32 {
@@ -56,11 +133,63 @@ fn kwasm_call_js_with_args_raw0(function_object: u32, this: u32, args: &[u32]) -
     }
 }
 
+/// Returned by the host when a call into Javascript threw an exception.
+/// The exception itself is retrieved with `kwasm_take_exception`.
+const EXCEPTION_THROWN: u32 = u32::MAX;
+
 /// Window.self
 /// Accesses the global scope.
 /// https://developer.mozilla.org/en-US/docs/Web/API/Window/self
 pub const JS_SELF: JSObject = JSObject(Cell::new(1));
 
+/// Javascript's `undefined`.
+/// Calls that return `undefined` return `None` instead of this.
+pub const JS_UNDEFINED: JSObject = JSObject(Cell::new(2));
+
+/// Javascript's `null`.
+pub const JS_NULL: JSObject = JSObject(Cell::new(3));
+
+/// Javascript's `true`.
+pub const JS_TRUE: JSObject = JSObject(Cell::new(4));
+
+/// Javascript's `false`.
+pub const JS_FALSE: JSObject = JSObject(Cell::new(5));
+
+/// Handles up to this index are reserved by the host and never freed.
+const LAST_RESERVED_INDEX: u32 = 5;
+
+/// The type of a Javascript value, as reported by `typeof`.
+/// Unlike `typeof`, `null` is reported as `Null` instead of `Object`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum JSType {
+    Undefined,
+    Null,
+    Boolean,
+    Number,
+    BigInt,
+    String,
+    Symbol,
+    Object,
+    Function,
+}
+
+impl JSType {
+    fn from_u32(value: u32) -> Self {
+        match value {
+            0 => JSType::Undefined,
+            1 => JSType::Null,
+            2 => JSType::Boolean,
+            3 => JSType::Number,
+            4 => JSType::BigInt,
+            5 => JSType::String,
+            6 => JSType::Symbol,
+            7 => JSType::Object,
+            8 => JSType::Function,
+            _ => unreachable!(),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct JSObjectDynamicInner(JSObject);
 
@@ -83,6 +212,9 @@ pub struct JSObjectInner(u32);
 impl JSObject {
     pub const NULL: Self = JSObject(Cell::new(0));
 
+    /// Gets a property of this object.
+    /// If the property is missing the null handle is returned.
+    /// If the property exists but is `undefined` then `JS_UNDEFINED` is returned.
     pub fn get_property(&self, string: &str) -> JSObjectDynamic {
         let string = JSString::new(string);
         unsafe {
@@ -92,6 +224,70 @@ impl JSObject {
         }
     }
 
+    /// Sets a property on this object.
+    /// Returns an error if the assignment throws, like when assigning to a frozen object.
+    pub fn set_property(&self, property: &str, value: &JSObject) -> Result<(), JSError> {
+        let result = unsafe {
+            kwasm_set_js_object_property(
+                self.index(),
+                property.as_ptr(),
+                property.len() as u32,
+                value.index(),
+            )
+        };
+        if result == EXCEPTION_THROWN {
+            Err(Self::take_exception())
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Deletes a property from this object.
+    /// Returns false if the property could not be deleted.
+    pub fn delete_property(&self, property: &str) -> bool {
+        unsafe {
+            kwasm_delete_js_object_property(self.index(), property.as_ptr(), property.len() as u32)
+                != 0
+        }
+    }
+
+    /// Checks if this object or its prototype chain has a property,
+    /// like Javascript's `in` operator.
+    pub fn has_property(&self, property: &str) -> bool {
+        unsafe {
+            kwasm_js_object_has_property(self.index(), property.as_ptr(), property.len() as u32)
+                != 0
+        }
+    }
+
+    /// Returns the names of this object's own enumerable properties, like `Object.keys`.
+    pub fn own_keys(&self) -> Vec<String> {
+        unsafe { kwasm_js_object_own_keys(self.index()) };
+
+        // Each key is passed as a little-endian u32 length followed by its UTF-8 bytes.
+        let data = crate::DATA_FROM_HOST.with(|d| d.take());
+        let mut keys = Vec::new();
+        let mut remaining = &data[..];
+        while remaining.len() >= 4 {
+            let (length, rest) = remaining.split_at(4);
+            let length = u32::from_le_bytes([length[0], length[1], length[2], length[3]]) as usize;
+            let (key, rest) = rest.split_at(length);
+            keys.push(String::from_utf8(key.to_vec()).unwrap());
+            remaining = rest;
+        }
+        keys
+    }
+
+    /// Creates a new empty object, like `{}`.
+    pub fn new_object() -> JSObjectDynamic {
+        unsafe { JSObject::new_raw(kwasm_new_object()) }
+    }
+
+    /// Creates a new empty array, like `[]`.
+    pub fn new_array() -> JSObjectDynamic {
+        unsafe { JSObject::new_raw(kwasm_new_array()) }
+    }
+
     pub fn null() -> JSObjectDynamic {
         JSObjectDynamic(Rc::new(JSObjectDynamicInner(JSObject(Cell::new(0)))))
     }
@@ -100,54 +296,197 @@ impl JSObject {
         self.0.borrow().get()
     }
 
-    // If this value is a u32, return it as a u32
+    /// Returns this value converted to a u32.
+    /// BigInts are wrapped to 32 bits, and values that can't be converted to a number are 0.
     pub fn get_value_u32(&self) -> u32 {
         unsafe { kwasm_get_js_object_value_u32(self.index()) }
     }
 
-    // If this value is a u32, return it as a u32
+    /// Returns this value converted to an f64.
+    /// Values that can't be converted to a number are NaN.
     pub fn get_value_f64(&self) -> f64 {
         unsafe { kwasm_get_js_object_value_f64(self.index()) }
     }
 
+    /// Returns this value converted to a bool with Javascript's truthiness rules.
+    pub fn get_value_bool(&self) -> bool {
+        unsafe { kwasm_get_js_object_value_bool(self.index()) != 0 }
+    }
+
+    /// Returns this value converted to an i32.
+    /// BigInts are wrapped to 32 bits, and values that can't be converted to a number are 0.
+    pub fn get_value_i32(&self) -> i32 {
+        unsafe { kwasm_get_js_object_value_i32(self.index()) }
+    }
+
+    /// Returns this value converted to an i64.
+    /// BigInts are truncated to 64 bits, other values are converted to a number first.
+    pub fn get_value_i64(&self) -> i64 {
+        unsafe { kwasm_get_js_object_value_i64(self.index()) }
+    }
+
+    /// Returns this value converted to an f32.
+    pub fn get_value_f32(&self) -> f32 {
+        self.get_value_f64() as f32
+    }
+
+    /// Returns the type of this value.
+    pub fn type_of(&self) -> JSType {
+        JSType::from_u32(unsafe { kwasm_js_object_type_of(self.index()) })
+    }
+
+    #[inline]
+    fn check_type(&self, expected: JSType) -> Result<(), JSType> {
+        let type_of = self.type_of();
+        if type_of == expected {
+            Ok(())
+        } else {
+            Err(type_of)
+        }
+    }
+
+    /// Returns this value if it is a boolean, otherwise returns its actual type.
+    pub fn try_get_value_bool(&self) -> Result<bool, JSType> {
+        self.check_type(JSType::Boolean)?;
+        Ok(self.get_value_bool())
+    }
+
+    // Returns this value if it's a number with no fractional part in `start..end`.
+    fn try_get_integer(&self, start: f64, end: f64) -> Result<f64, JSType> {
+        self.check_type(JSType::Number)?;
+        let value = self.get_value_f64();
+        if value.fract() == 0.0 && value >= start && value < end {
+            Ok(value)
+        } else {
+            Err(JSType::Number)
+        }
+    }
+
+    /// Returns this value if it is a number that's exactly an i32, otherwise returns its actual type.
+    /// Numbers with a fractional part or outside the range of an i32 return `JSType::Number`.
+    pub fn try_get_value_i32(&self) -> Result<i32, JSType> {
+        self.try_get_integer(i32::MIN as f64, -(i32::MIN as f64))
+            .map(|value| value as i32)
+    }
+
+    /// Returns this value if it is a number that's exactly a u32, otherwise returns its actual type.
+    /// Numbers with a fractional part or outside the range of a u32 return `JSType::Number`.
+    pub fn try_get_value_u32(&self) -> Result<u32, JSType> {
+        self.try_get_integer(0.0, u32::MAX as f64 + 1.0)
+            .map(|value| value as u32)
+    }
+
+    /// Returns this value if it is a BigInt or a number that's exactly an i64,
+    /// otherwise returns its actual type.
+    /// BigInts are truncated to 64 bits. Numbers with a fractional part or outside the range
+    /// of an i64 return `JSType::Number`.
+    pub fn try_get_value_i64(&self) -> Result<i64, JSType> {
+        match self.type_of() {
+            JSType::BigInt => Ok(self.get_value_i64()),
+            _ => self
+                .try_get_integer(i64::MIN as f64, -(i64::MIN as f64))
+                .map(|value| value as i64),
+        }
+    }
+
+    /// Returns this value if it is a number, otherwise returns its actual type.
+    pub fn try_get_value_f32(&self) -> Result<f32, JSType> {
+        self.check_type(JSType::Number)?;
+        Ok(self.get_value_f32())
+    }
+
+    /// Returns this value if it is a number, otherwise returns its actual type.
+    pub fn try_get_value_f64(&self) -> Result<f64, JSType> {
+        self.check_type(JSType::Number)?;
+        Ok(self.get_value_f64())
+    }
+
+    /// Returns this value if it is a string, otherwise returns its actual type.
+    pub fn try_get_value_string(&self) -> Result<String, JSType> {
+        self.get_value_string().ok_or_else(|| self.type_of())
+    }
+
+    /// If this value is a string, return it as a `String`.
+    /// Returns `None` for any other type of value.
+    /// Use `to_string` to convert any value with Javascript's `String(value)`.
+    pub fn get_value_string(&self) -> Option<String> {
+        if unsafe { kwasm_get_js_object_value_string(self.index()) } == 0 {
+            None
+        } else {
+            Some(crate::get_string_from_host())
+        }
+    }
+
     /// Replaces the inner JSObject with the new JSObject.
     pub fn swap(&self, object: &JSObject) {
         self.0.swap(&object.0)
     }
 
+    /// Returns true if this is the null handle, which doesn't refer to any value.
+    /// Javascript sees the null handle as `null`.
     pub fn is_null(&self) -> bool {
         self.index() == 0
     }
 
+    /// Returns true if this is Javascript's `undefined`.
+    pub fn is_undefined(&self) -> bool {
+        self.index() == JS_UNDEFINED.index()
+    }
+
+    /// Returns true if this is Javascript's `null`, including the null handle.
+    /// Not to be confused with `is_null`, which only checks for the null handle.
+    pub fn is_js_null(&self) -> bool {
+        self.is_null() || self.index() == JS_NULL.index()
+    }
+
     pub unsafe fn new_raw(index: u32) -> JSObjectDynamic {
         JSObjectDynamic(Rc::new(JSObjectDynamicInner(JSObject(Cell::new(index)))))
     }
 
+    fn take_exception() -> JSError {
+        let exception = unsafe { JSObject::new_raw(kwasm_take_exception()) };
+        JSError::from_exception(exception)
+    }
+
     #[inline]
-    fn check_result(result: u32) -> Option<JSObjectDynamic> {
-        if result == 0 {
-            None
+    pub(crate) fn check_result(result: u32) -> Result<Option<JSObjectDynamic>, JSError> {
+        // Debug builds check for worker stack overflows every time they return from Javascript.
+        #[cfg(all(debug_assertions, target_feature = "atomics"))]
+        crate::web_worker::check_stack_overflow_sampled();
+
+        if result == EXCEPTION_THROWN {
+            Err(Self::take_exception())
+        } else if result == 0 {
+            Ok(None)
         } else {
-            Some(JSObjectDynamic(Rc::new(JSObjectDynamicInner(JSObject(
-                Cell::new(result),
+            Ok(Some(JSObjectDynamic(Rc::new(JSObjectDynamicInner(
+                JSObject(Cell::new(result)),
             )))))
         }
     }
 
     /// Call a function with each u32 passed as a separate argument to the JavaScript side.
-    pub fn call_raw(&self, this: &JSObject, args: &[u32]) -> Option<JSObjectDynamic> {
+    pub fn call_raw(
+        &self,
+        this: &JSObject,
+        args: &[u32],
+    ) -> Result<Option<JSObjectDynamic>, JSError> {
         let result = kwasm_call_js_with_args_raw0(self.index(), this.index(), args);
         Self::check_result(result)
     }
 
     /// Call this as a function with one arg.
-    pub fn call(&self, this: &JSObject) -> Option<JSObjectDynamic> {
+    pub fn call(&self, this: &JSObject) -> Result<Option<JSObjectDynamic>, JSError> {
         let result = kwasm_call_js_with_args0(self.index(), this.index(), &[]);
         Self::check_result(result)
     }
 
     /// Call this as a function with one arg.
-    pub fn call_1_arg(&self, this: &JSObject, argument: &JSObject) -> Option<JSObjectDynamic> {
+    pub fn call_1_arg(
+        &self,
+        this: &JSObject,
+        argument: &JSObject,
+    ) -> Result<Option<JSObjectDynamic>, JSError> {
         let result = kwasm_call_js_with_args0(self.index(), this.index(), &[argument.index()]);
 
         Self::check_result(result)
@@ -159,17 +498,123 @@ impl JSObject {
         this: &JSObject,
         arg0: &JSObject,
         arg1: &JSObject,
-    ) -> Option<JSObjectDynamic> {
+    ) -> Result<Option<JSObjectDynamic>, JSError> {
         let result =
             kwasm_call_js_with_args0(self.index(), this.index(), &[arg0.index(), arg1.index()]);
 
         Self::check_result(result)
     }
+
+    /// Call this as a function with any number of args.
+    /// Returns `None` if the function returns `undefined`.
+    pub fn call_with_args(
+        &self,
+        this: &JSObject,
+        args: &[&JSObject],
+    ) -> Result<Option<JSObjectDynamic>, JSError> {
+        let args: Vec<u32> = args.iter().map(|a| a.index()).collect();
+        let result = kwasm_call_js_with_args0(self.index(), this.index(), &args);
+        Self::check_result(result)
+    }
+
+    /// Call this as a function with args that don't need handles,
+    /// like numbers and strings.
+    /// The args are passed to the host in a single call.
+    ///
+    /// ```no_run
+    /// # use kwasm::*;
+    /// let log = JS_SELF.get_property("console").get_property("log");
+    /// log.call_typed(&JSObject::NULL, &["x is".into_js_arg(), 10.0_f64.into_js_arg()]).unwrap();
+    /// ```
+    pub fn call_typed(
+        &self,
+        this: &JSObject,
+        args: &[JSArg],
+    ) -> Result<Option<JSObjectDynamic>, JSError> {
+        let result = unsafe {
+            kwasm_call_js_with_typed_args(
+                self.index(),
+                this.index(),
+                args.as_ptr() as *const c_void,
+                args.len() as u32,
+            )
+        };
+        Self::check_result(result)
+    }
+
+    /// Like `call_method` but with args that don't need handles.
+    pub fn call_method_typed(
+        &self,
+        method: &str,
+        args: &[JSArg],
+    ) -> Result<Option<JSObjectDynamic>, JSError> {
+        let result = unsafe {
+            kwasm_call_js_method_with_typed_args(
+                self.index(),
+                method.as_ptr(),
+                method.len() as u32,
+                args.as_ptr() as *const c_void,
+                args.len() as u32,
+            )
+        };
+        Self::check_result(result)
+    }
+
+    /// Calls this as a constructor, like Javascript's `new`.
+    pub fn construct(&self, args: &[&JSObject]) -> Result<JSObjectDynamic, JSError> {
+        let args: Vec<u32> = args.iter().map(|a| a.index()).collect();
+        let result = unsafe {
+            kwasm_construct_js_object(
+                self.index(),
+                args.as_ptr() as *const c_void,
+                args.len() as u32,
+            )
+        };
+        // A constructor always returns an object.
+        Self::check_result(result).map(|object| object.unwrap())
+    }
+
+    /// Calls the method with the given name on this object, with this object as `this`.
+    /// The method is looked up and called without an extra round trip through Rust.
+    pub fn call_method(
+        &self,
+        method: &str,
+        args: &[&JSObject],
+    ) -> Result<Option<JSObjectDynamic>, JSError> {
+        let args: Vec<u32> = args.iter().map(|a| a.index()).collect();
+        let result = unsafe {
+            kwasm_call_js_method(
+                self.index(),
+                method.as_ptr(),
+                method.len() as u32,
+                args.as_ptr() as *const c_void,
+                args.len() as u32,
+            )
+        };
+        Self::check_result(result)
+    }
+}
+
+/// Formats the value with Javascript's `String(value)`.
+impl fmt::Display for JSObject {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        unsafe { kwasm_js_object_to_string(self.index()) };
+        f.write_str(&crate::get_string_from_host())
+    }
+}
+
+impl fmt::Display for JSObjectDynamic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self.deref(), f)
+    }
 }
 
 impl Drop for JSObjectDynamicInner {
     fn drop(&mut self) {
-        unsafe { kwasm_free_js_object(self.0.index()) }
+        // Reserved handles are never freed so don't bother calling into the host.
+        if self.0.index() > LAST_RESERVED_INDEX {
+            unsafe { kwasm_free_js_object(self.0.index()) }
+        }
     }
 }
 
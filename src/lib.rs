@@ -7,6 +7,9 @@
 //! It can work alongside `wasm-bindgen` or stand-alone.
 //! Kwasm uses eval to initialize Javascript code from Rust libraries.
 
+// Builds with atomics already require nightly for `-Z build-std`.
+#![cfg_attr(target_feature = "atomics", feature(stdarch_wasm_atomic_wait))]
+
 use std::cell::RefCell;
 use std::ops::Deref;
 
@@ -21,11 +24,32 @@ pub mod libraries {
     pub use instant::*;
 }
 
+mod event_listener;
+pub mod executor;
+mod js_arg;
+mod js_closure;
+mod js_error;
+mod js_future;
 mod js_object;
+#[cfg(not(target_feature = "atomics"))]
+pub mod message_worker;
 mod panic_hook;
-
+pub mod ring_buffer;
+#[cfg(target_feature = "atomics")]
+mod transferable_js_object;
+
+pub use event_listener::*;
+pub use executor::spawn_local;
+pub use js_arg::*;
+pub use js_closure::*;
+pub use js_error::*;
+pub use js_future::*;
 pub use js_object::*;
+#[cfg(target_feature = "atomics")]
+pub use transferable_js_object::*;
 
+#[cfg(target_feature = "atomics")]
+pub mod sync;
 #[cfg(target_feature = "atomics")]
 pub mod web_worker;
 
@@ -56,6 +80,7 @@ pub fn available_threads() -> u32 {
             result
         "#,
     )
+    .unwrap()
     .unwrap();
     result.get_value_u32()
 }
@@ -91,9 +116,11 @@ pub(crate) extern "C" fn kwasm_alloc_thread_local_storage() -> u32 {
     unsafe {
         THREAD_LOCAL_STORAGE_METADATA_INIT.call_once(|| {
             THREAD_LOCAL_STORAGE_SIZE = eval("self.kwasm_exports.__tls_size.value")
+                .unwrap()
                 .unwrap()
                 .get_value_u32();
             THREAD_LOCAL_STORAGE_ALIGNMENT = eval("self.kwasm_exports.__tls_align.value")
+                .unwrap()
                 .unwrap()
                 .get_value_u32();
         });
@@ -159,6 +186,7 @@ impl JSObjectFromString {
         if self.inner_object.is_null() {
             self.inner_object.swap(
                 &eval(&self.source)
+                    .unwrap_or_else(|e| panic!("JSObjectFromString code source threw: {}", e))
                     .unwrap_or_else(|| panic!("JSObjectFromString code source returned null")),
             )
         }
@@ -186,3 +214,25 @@ pub fn get_string_from_host() -> String {
         String::from_utf8(d).unwrap()
     })
 }
+
+thread_local! {
+    static COPY_BYTES_TO_CLIENT: JSObjectFromString = JSObjectFromString::new(
+        r#"
+        function copy_bytes_to_client(bytes) {
+            if (bytes instanceof ArrayBuffer) {
+                bytes = new Uint8Array(bytes);
+            }
+            let pointer = self.kwasm_exports.kwasm_reserve_space(bytes.byteLength);
+            let destination = new Uint8Array(self.kwasm_memory.buffer, pointer, bytes.byteLength);
+            destination.set(bytes);
+        };
+        copy_bytes_to_client
+        "#,
+    );
+}
+
+/// Copies the contents of a Javascript `Uint8Array` or `ArrayBuffer`.
+pub(crate) fn get_bytes_from_js(bytes: &JSObject) -> Result<Vec<u8>, JSError> {
+    COPY_BYTES_TO_CLIENT.with(|f| f.call_1_arg(&JSObject::NULL, bytes))?;
+    Ok(DATA_FROM_HOST.with(|d| d.take()))
+}
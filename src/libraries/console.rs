@@ -6,21 +6,25 @@ thread_local! {
 }
 
 pub fn log(string: &str) {
-    let js_string_object = JSString::new(string);
-    log_js_string(&js_string_object);
+    CONSOLE_LOG.with(|f| {
+        let _ = f.call_typed(&JSObject::NULL, &[string.into_js_arg()]);
+    })
 }
 
 pub fn error(string: &str) {
-    let js_string_object = JSString::new(string);
-    error_js_string(&js_string_object);
+    CONSOLE_ERROR.with(|f| {
+        let _ = f.call_typed(&JSObject::NULL, &[string.into_js_arg()]);
+    })
 }
 
 pub fn log_js_string(js_string: &JSString) {
-    CONSOLE_LOG.with(|f| f.call_1_arg(&JSObject::NULL, js_string));
+    CONSOLE_LOG.with(|f| {
+        let _ = f.call_1_arg(&JSObject::NULL, js_string);
+    })
 }
 
 pub fn error_js_string(js_string: &JSString) {
     CONSOLE_ERROR.with(|f| {
-        f.call_1_arg(&JSObject::NULL, js_string);
+        let _ = f.call_1_arg(&JSObject::NULL, js_string);
     })
 }
@@ -4,7 +4,9 @@ thread_local! {
     static EVAL_FUNCTION: JSObject = JSObject::NULL;
 }
 
-pub fn eval(source: &str) -> Option<JSObjectDynamic> {
+/// Evaluates Javascript source code.
+/// A syntax error or an exception thrown by the code is returned as a `JSError`.
+pub fn eval(source: &str) -> Result<Option<JSObjectDynamic>, JSError> {
     let source_str: JSString = JSString::new(source);
 
     EVAL_FUNCTION.with(|e| {
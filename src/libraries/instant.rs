@@ -6,7 +6,10 @@ thread_local! {
     static PERFORMANCE_NOW: JSObjectFromString = JSObjectFromString::new("function now() { return performance.now() }; now");
 }
 pub fn now() -> f64 {
-    let result = PERFORMANCE_NOW.with(|f| f.call(&JSObject::NULL)).unwrap();
+    let result = PERFORMANCE_NOW
+        .with(|f| f.call(&JSObject::NULL))
+        .unwrap()
+        .unwrap();
     result.get_value_f64()
 }
 
@@ -0,0 +1,143 @@
+//! Web Workers for builds without atomics.
+//!
+//! Without shared memory each worker instantiates the Wasm module with its own memory, so
+//! nothing can be shared with it. Instead a worker is started by calling one of the module's
+//! exported functions, and it communicates with the thread that spawned it by sending bytes
+//! with `postMessage`. This works on pages that aren't cross-origin isolated.
+//!
+//! ```no_run
+//! #[no_mangle]
+//! pub extern "C" fn decode_worker() {
+//!     let payload = kwasm::message_worker::take_payload();
+//!     kwasm::message_worker::post_message(&payload);
+//! }
+//!
+//! let mut worker = kwasm::message_worker::spawn("decode_worker", b"compressed data");
+//! worker.on_message(|bytes| kwasm::libraries::log(&format!("{} bytes", bytes.len())));
+//! ```
+
+use crate::*;
+use std::cell::RefCell;
+
+thread_local! {
+    static POST_MESSAGE: JSObjectFromString = JSObjectFromString::new(
+        r#"
+        function post_message(target, pointer, length) {
+            const data = new Uint8Array(self.kwasm_memory.buffer, pointer, length).slice();
+            target.postMessage(data, [data.buffer]);
+        };
+        post_message
+        "#,
+    );
+    static SET_ON_MESSAGE: JSObjectFromString = JSObjectFromString::new(
+        r#"
+        function set_on_message(listener) {
+            onmessage = e => listener(e.data);
+
+            // Pass along messages that arrived before there was a listener.
+            const queued_messages = self.kwasm_queued_messages;
+            self.kwasm_queued_messages = [];
+            queued_messages.forEach(listener);
+        };
+        set_on_message
+        "#,
+    );
+    // The listener passed to `on_message` on a worker.
+    static ON_MESSAGE: RefCell<Option<JSClosure>> = const { RefCell::new(None) };
+}
+
+/// A worker started with `spawn`. The worker is terminated when this is dropped.
+pub struct MessageWorker {
+    worker: JSObjectDynamic,
+    listener: Option<EventListenerGuard>,
+}
+
+/// Starts a Web Worker that calls the exported function named `entry_point`.
+///
+/// The entry point must be a `#[no_mangle] pub extern "C" fn()` that takes no arguments.
+/// `payload` is copied to the worker and can be retrieved there with `take_payload`.
+pub fn spawn(entry_point: &str, payload: &[u8]) -> MessageWorker {
+    let worker = unsafe {
+        JSObject::new_raw(kwasm_new_message_worker(
+            entry_point.as_ptr(),
+            entry_point.len() as u32,
+            payload.as_ptr(),
+            payload.len() as u32,
+        ))
+    };
+    MessageWorker {
+        worker,
+        listener: None,
+    }
+}
+
+impl MessageWorker {
+    /// Sends a copy of `bytes` to the worker.
+    pub fn post_message(&self, bytes: &[u8]) {
+        post_message_to(&self.worker, bytes);
+    }
+
+    /// Calls `listener` with each message the worker sends with `message_worker::post_message`.
+    /// Replaces the previous listener.
+    pub fn on_message(&mut self, mut listener: impl FnMut(Vec<u8>) + 'static) {
+        // Drop the previous listener first so it's removed before the new one is added.
+        self.listener = None;
+        self.listener = Some(
+            self.worker
+                .add_event_listener("message", move |event| {
+                    listener(get_bytes_from_js(&event.get_property("data")).unwrap())
+                })
+                .unwrap(),
+        );
+    }
+
+    /// The Javascript `Worker`.
+    pub fn worker(&self) -> &JSObjectDynamic {
+        &self.worker
+    }
+}
+
+impl Drop for MessageWorker {
+    fn drop(&mut self) {
+        let _ = self.worker.call_method("terminate", &[]);
+    }
+}
+
+/// On a worker, returns the payload passed to `spawn`.
+/// The payload can only be taken once, and must be taken before calling into Javascript.
+pub fn take_payload() -> Vec<u8> {
+    DATA_FROM_HOST.with(|d| d.take())
+}
+
+/// On a worker, sends a copy of `bytes` to the thread that spawned it.
+pub fn post_message(bytes: &[u8]) {
+    post_message_to(&JS_SELF, bytes);
+}
+
+/// On a worker, calls `listener` with each message sent with `MessageWorker::post_message`.
+/// Messages sent before this is called are passed to the first listener.
+pub fn on_message(mut listener: impl FnMut(Vec<u8>) + 'static) {
+    let listener = JSClosure::new(move |args| {
+        listener(get_bytes_from_js(&args[0]).unwrap());
+        None
+    });
+    SET_ON_MESSAGE
+        .with(|f| f.call_1_arg(&JSObject::NULL, &listener))
+        .unwrap();
+    ON_MESSAGE.with(|o| *o.borrow_mut() = Some(listener));
+}
+
+fn post_message_to(target: &JSObject, bytes: &[u8]) {
+    POST_MESSAGE
+        .with(|f| {
+            f.call_typed(
+                &JSObject::NULL,
+                &[
+                    target.into_js_arg(),
+                    (bytes.as_ptr() as u32).into_js_arg(),
+                    (bytes.len() as u32).into_js_arg(),
+                ],
+            )
+        })
+        .unwrap();
+}
@@ -1,8 +1,18 @@
 use std::panic;
 
 fn hook_impl(info: &panic::PanicInfo) {
-    let message = info.to_string();
+    #[allow(unused_mut)]
+    let mut message = info.to_string();
+
+    // A panic in a worker may be the result of its stack overflowing.
+    #[cfg(target_feature = "atomics")]
+    if let Some(stack_overflow) = crate::web_worker::take_stack_overflow_message() {
+        message = format!("{} (detected {})", message, stack_overflow);
+    }
+
     crate::libraries::console::error(&message);
+    #[cfg(target_feature = "atomics")]
+    crate::web_worker::record_panic(&message);
 }
 
 /// Sets up a panic hook to print a slightly more useful error-message to the console.
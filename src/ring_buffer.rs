@@ -0,0 +1,201 @@
+//! A single-producer, single-consumer ring buffer of bytes in Wasm memory.
+//!
+//! The buffer can be read directly from Javascript, for example from an AudioWorklet,
+//! with the `KwasmRingBufferReader` class exported by `kwasm.js`.
+//!
+//! The memory layout at `RingBufferReader::address()` is:
+//!
+//! | Offset | Type           | Contents                                              |
+//! |--------|----------------|-------------------------------------------------------|
+//! | 0      | u32            | `head`: the total number of bytes read                |
+//! | 4      | u32            | `tail`: the total number of bytes written             |
+//! | 8      | u32            | `capacity`: the size of the data in bytes             |
+//! | 12     | u32            | Reserved                                              |
+//! | 16     | [u8; capacity] | The data                                              |
+//!
+//! All values are little-endian. `head` and `tail` wrap around at `u32::MAX`, so the number
+//! of bytes ready to read is `tail - head` with wrapping, and a position's index into the data is
+//! `position & (capacity - 1)`. `capacity` is a power of two.
+//! Only the reader changes `head` and only the writer changes `tail`, both with atomic stores
+//! made after the data they cover has been read or written.
+
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+
+const HEADER_SIZE: usize = 16;
+
+#[repr(C)]
+struct Header {
+    head: AtomicU32,
+    tail: AtomicU32,
+    capacity: u32,
+    _reserved: u32,
+}
+
+struct Allocation {
+    pointer: *mut u8,
+    layout: std::alloc::Layout,
+}
+
+// The header is only accessed atomically and each half of the data
+// is only accessed by one of the `RingBufferWriter` or `RingBufferReader`.
+unsafe impl Send for Allocation {}
+unsafe impl Sync for Allocation {}
+
+impl Drop for Allocation {
+    fn drop(&mut self) {
+        unsafe {
+            std::alloc::dealloc(self.pointer, self.layout);
+        }
+    }
+}
+
+impl Allocation {
+    fn header(&self) -> &Header {
+        unsafe { &*(self.pointer as *const Header) }
+    }
+
+    fn data(&self) -> *mut u8 {
+        unsafe { self.pointer.add(HEADER_SIZE) }
+    }
+
+    fn capacity(&self) -> usize {
+        self.header().capacity as usize
+    }
+
+    fn len(&self) -> usize {
+        let header = self.header();
+        let head = header.head.load(Ordering::Acquire);
+        let tail = header.tail.load(Ordering::Acquire);
+        tail.wrapping_sub(head) as usize
+    }
+}
+
+/// Writes to a ring buffer created with `new`.
+pub struct RingBufferWriter {
+    allocation: Arc<Allocation>,
+}
+
+/// Reads from a ring buffer created with `new`.
+///
+/// To read from Javascript instead, pass `address()` and the Wasm memory to a
+/// `KwasmRingBufferReader` and keep this alive without reading from it.
+pub struct RingBufferReader {
+    allocation: Arc<Allocation>,
+}
+
+/// Creates a ring buffer that holds at least `capacity` bytes.
+/// The capacity is rounded up to a power of two.
+/// Panics if `capacity` is more than 2^31 bytes.
+pub fn new(capacity: usize) -> (RingBufferWriter, RingBufferReader) {
+    // Checked before rounding up, which would overflow for larger capacities.
+    assert!(
+        capacity <= 1 << 31,
+        "ring buffer capacity must fit in a u32"
+    );
+    let capacity = capacity.max(1).next_power_of_two();
+
+    let layout = std::alloc::Layout::from_size_align(HEADER_SIZE + capacity, 16).unwrap();
+    let pointer = unsafe { std::alloc::alloc_zeroed(layout) };
+    if pointer.is_null() {
+        std::alloc::handle_alloc_error(layout);
+    }
+    unsafe {
+        (*(pointer as *mut Header)).capacity = capacity as u32;
+    }
+
+    let allocation = Arc::new(Allocation { pointer, layout });
+    (
+        RingBufferWriter {
+            allocation: allocation.clone(),
+        },
+        RingBufferReader { allocation },
+    )
+}
+
+impl RingBufferWriter {
+    /// Writes as much of `data` as fits and returns how many bytes were written.
+    pub fn write(&mut self, data: &[u8]) -> usize {
+        let allocation = &self.allocation;
+        let header = allocation.header();
+        let capacity = allocation.capacity();
+
+        let head = header.head.load(Ordering::Acquire);
+        let tail = header.tail.load(Ordering::Relaxed);
+        let free_space = capacity - tail.wrapping_sub(head) as usize;
+        let count = free_space.min(data.len());
+
+        // The data may wrap around the end of the buffer.
+        let start = tail as usize & (capacity - 1);
+        let first = count.min(capacity - start);
+        unsafe {
+            std::ptr::copy_nonoverlapping(data.as_ptr(), allocation.data().add(start), first);
+            std::ptr::copy_nonoverlapping(
+                data.as_ptr().add(first),
+                allocation.data(),
+                count - first,
+            );
+        }
+
+        header
+            .tail
+            .store(tail.wrapping_add(count as u32), Ordering::Release);
+        count
+    }
+
+    /// The number of bytes that can be written without overwriting unread data.
+    pub fn free_space(&self) -> usize {
+        self.allocation.capacity() - self.allocation.len()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.allocation.capacity()
+    }
+}
+
+impl RingBufferReader {
+    /// Reads as many bytes as are ready into `data` and returns how many were read.
+    pub fn read(&mut self, data: &mut [u8]) -> usize {
+        let allocation = &self.allocation;
+        let header = allocation.header();
+        let capacity = allocation.capacity();
+
+        let head = header.head.load(Ordering::Relaxed);
+        let tail = header.tail.load(Ordering::Acquire);
+        let count = (tail.wrapping_sub(head) as usize).min(data.len());
+
+        let start = head as usize & (capacity - 1);
+        let first = count.min(capacity - start);
+        unsafe {
+            std::ptr::copy_nonoverlapping(allocation.data().add(start), data.as_mut_ptr(), first);
+            std::ptr::copy_nonoverlapping(
+                allocation.data(),
+                data.as_mut_ptr().add(first),
+                count - first,
+            );
+        }
+
+        header
+            .head
+            .store(head.wrapping_add(count as u32), Ordering::Release);
+        count
+    }
+
+    /// The number of bytes ready to read.
+    pub fn len(&self) -> usize {
+        self.allocation.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.allocation.capacity()
+    }
+
+    /// The address of the ring buffer's header in Wasm memory.
+    pub fn address(&self) -> u32 {
+        self.allocation.pointer as u32
+    }
+}
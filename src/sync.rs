@@ -0,0 +1,367 @@
+//! Blocking synchronization primitives for Web Workers.
+//!
+//! Workers block with `memory.atomic.wait32`. Each primitive also has `_async` methods that
+//! spin briefly and then wait with `Atomics.waitAsync`.
+//!
+//! Don't use the blocking methods on the main thread. It isn't allowed to block, so they spin
+//! there instead, which stops its event loop. Workers can't start and `run_on_main_thread`
+//! can't run while it's stopped, so waiting on them deadlocks. Use the `_async` methods instead,
+//! and `lock` to lock a `std::sync::Mutex` that's shared with workers.
+//!
+//! Channels that work the same way are in `mpsc` and `mpmc`.
+
+use crate::executor;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+pub mod mpmc;
+pub mod mpsc;
+
+// How many times the `_async` methods check for a change before waiting with `Atomics.waitAsync`.
+const SPIN_LIMIT: u32 = 100;
+
+/// Blocks while the value at `address` is `value`.
+/// Returns early if woken, so callers should check their condition again.
+fn wait(address: &AtomicI32, value: i32) {
+    if crate::web_worker::is_worker() {
+        unsafe {
+            core::arch::wasm32::memory_atomic_wait32(
+                address as *const AtomicI32 as *mut i32,
+                value,
+                -1,
+            );
+        }
+    } else {
+        while address.load(Ordering::SeqCst) == value {
+            std::hint::spin_loop();
+        }
+    }
+}
+
+/// Waits while the value at `address` is `value` without blocking.
+async fn wait_async(address: &AtomicI32, value: i32) {
+    for _ in 0..SPIN_LIMIT {
+        if address.load(Ordering::SeqCst) != value {
+            return;
+        }
+        std::hint::spin_loop();
+    }
+    let _ = executor::wait_async(address, value).await;
+}
+
+/// Wakes up to `count` threads waiting on `address` without changing its value.
+fn wake(address: &AtomicI32, count: u32) {
+    unsafe {
+        core::arch::wasm32::memory_atomic_notify(address as *const AtomicI32 as *mut i32, count);
+    }
+}
+
+/// Changes the value at `address` and wakes every thread waiting on it.
+///
+/// Waiting with `Atomics.waitAsync` can't be cancelled, so waking a single thread could wake
+/// an `_async` future that was dropped instead of a thread that's still waiting.
+fn notify_all(address: &AtomicI32) {
+    address.fetch_add(1, Ordering::SeqCst);
+    wake(address, u32::MAX);
+}
+
+// The values of `Parker::state`.
+const EMPTY: i32 = 0;
+const NOTIFIED: i32 = 1;
+const PARKED: i32 = -1;
+
+struct Parker {
+    state: AtomicI32,
+}
+
+thread_local! {
+    static CURRENT_PARKER: Arc<Parker> = Arc::new(Parker {
+        state: AtomicI32::new(EMPTY),
+    });
+}
+
+/// A handle used to unpark a thread.
+#[derive(Clone)]
+pub struct Thread {
+    parker: Arc<Parker>,
+}
+
+impl Thread {
+    /// Wakes the thread if it's parked, otherwise its next call to `park` returns immediately.
+    pub fn unpark(&self) {
+        if self.parker.state.swap(NOTIFIED, Ordering::SeqCst) == PARKED {
+            // A `park_async` future that was dropped may also be waiting, see `notify_all`.
+            wake(&self.parker.state, u32::MAX);
+        }
+    }
+}
+
+/// Returns a handle to the current thread.
+pub fn current() -> Thread {
+    Thread {
+        parker: CURRENT_PARKER.with(|p| p.clone()),
+    }
+}
+
+impl Parker {
+    // Takes the notification if there is one, otherwise marks the thread as parked.
+    fn take_notification(&self) -> bool {
+        match self
+            .state
+            .compare_exchange(EMPTY, PARKED, Ordering::SeqCst, Ordering::SeqCst)
+        {
+            Ok(_) => false,
+            // A `park_async` future dropped while waiting leaves the state `PARKED`.
+            Err(PARKED) => false,
+            Err(_) => {
+                // Only the parked thread changes the state away from `NOTIFIED`.
+                self.state.store(EMPTY, Ordering::SeqCst);
+                true
+            }
+        }
+    }
+}
+
+/// Blocks until the current thread is unparked with `Thread::unpark`.
+/// If the thread was unparked since it last parked this returns immediately.
+pub fn park() {
+    let parker = CURRENT_PARKER.with(|p| p.clone());
+    while !parker.take_notification() {
+        wait(&parker.state, PARKED);
+    }
+}
+
+/// Like `park` but waits without blocking.
+pub async fn park_async() {
+    let parker = CURRENT_PARKER.with(|p| p.clone());
+    while !parker.take_notification() {
+        wait_async(&parker.state, PARKED).await;
+    }
+}
+
+/// Locks `mutex` on any thread.
+///
+/// `Mutex::lock` traps on the main thread if it has to wait, so the main thread spins
+/// with `try_lock` instead. The lock should only be held briefly.
+/// Panics if `mutex` is poisoned.
+pub fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    executor::lock(mutex)
+}
+
+/// A condition variable for use with `std::sync::Mutex`.
+///
+/// `std::sync::MutexGuard` doesn't expose its `Mutex`, so it's passed to `wait` as well.
+/// On the main thread, get the guard for `wait_async` with `lock`.
+pub struct Condvar {
+    // Changed by each notification.
+    state: AtomicI32,
+}
+
+impl Default for Condvar {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Condvar {
+    pub const fn new() -> Self {
+        Self {
+            state: AtomicI32::new(0),
+        }
+    }
+
+    /// Unlocks `guard` and blocks until notified, then locks `mutex` again.
+    /// Like `std::sync::Condvar` this can wake spuriously, so check the condition in a loop.
+    pub fn wait<'a, T>(&self, guard: MutexGuard<'a, T>, mutex: &'a Mutex<T>) -> MutexGuard<'a, T> {
+        // Read the state before unlocking so a notification sent after unlocking isn't missed.
+        let state = self.state.load(Ordering::SeqCst);
+        drop(guard);
+        wait(&self.state, state);
+        lock(mutex)
+    }
+
+    /// Like `wait` but waits without blocking.
+    pub async fn wait_async<'a, T>(
+        &self,
+        guard: MutexGuard<'a, T>,
+        mutex: &'a Mutex<T>,
+    ) -> MutexGuard<'a, T> {
+        let state = self.state.load(Ordering::SeqCst);
+        drop(guard);
+        wait_async(&self.state, state).await;
+        lock(mutex)
+    }
+
+    /// Wakes at least one thread waiting on this.
+    ///
+    /// Every waiting thread is woken, because a single wake could be taken by a `wait_async`
+    /// future that was dropped. The others return from `wait` as a spurious wakeup.
+    pub fn notify_one(&self) {
+        notify_all(&self.state);
+    }
+
+    /// Wakes every thread waiting on this.
+    pub fn notify_all(&self) {
+        notify_all(&self.state);
+    }
+}
+
+struct BarrierState {
+    arrived: usize,
+    generation: i32,
+}
+
+/// Blocks threads until `n` of them have called `wait`.
+pub struct Barrier {
+    n: usize,
+    state: Mutex<BarrierState>,
+    // A copy of `BarrierState::generation` that waiting threads can wait on.
+    generation: AtomicI32,
+}
+
+/// Returned by `Barrier::wait`.
+#[derive(Debug, Clone, Copy)]
+pub struct BarrierWaitResult {
+    is_leader: bool,
+}
+
+impl BarrierWaitResult {
+    /// Returns true for exactly one of the threads released by the barrier.
+    pub fn is_leader(&self) -> bool {
+        self.is_leader
+    }
+}
+
+impl Barrier {
+    pub fn new(n: usize) -> Self {
+        Self {
+            n,
+            state: Mutex::new(BarrierState {
+                arrived: 0,
+                generation: 0,
+            }),
+            generation: AtomicI32::new(0),
+        }
+    }
+
+    /// Blocks until `n` threads have called `wait`. The barrier can then be used again.
+    pub fn wait(&self) -> BarrierWaitResult {
+        match self.arrive() {
+            Ok(result) => result,
+            Err(generation) => {
+                while self.generation.load(Ordering::SeqCst) == generation {
+                    wait(&self.generation, generation);
+                }
+                BarrierWaitResult { is_leader: false }
+            }
+        }
+    }
+
+    /// Like `wait` but waits without blocking.
+    pub async fn wait_async(&self) -> BarrierWaitResult {
+        match self.arrive() {
+            Ok(result) => result,
+            Err(generation) => {
+                while self.generation.load(Ordering::SeqCst) == generation {
+                    wait_async(&self.generation, generation).await;
+                }
+                BarrierWaitResult { is_leader: false }
+            }
+        }
+    }
+
+    // Returns the generation to wait for the end of, unless this thread released the barrier.
+    fn arrive(&self) -> Result<BarrierWaitResult, i32> {
+        let mut state = lock(&self.state);
+        let generation = state.generation;
+        state.arrived += 1;
+        if state.arrived < self.n {
+            return Err(generation);
+        }
+
+        state.arrived = 0;
+        state.generation = generation.wrapping_add(1);
+        self.generation.store(state.generation, Ordering::SeqCst);
+        wake(&self.generation, u32::MAX);
+        Ok(BarrierWaitResult { is_leader: true })
+    }
+}
+
+/// Waits for a group of threads to finish.
+///
+/// Each thread is given a clone of the `WaitGroup` and drops it when it's done.
+///
+/// ```no_run
+/// # async fn f() {
+/// let wait_group = kwasm::sync::WaitGroup::new();
+/// for _ in 0..4 {
+///     let wait_group = wait_group.clone();
+///     kwasm::web_worker::spawn(move || {
+///         // Do some work.
+///         drop(wait_group);
+///     });
+/// }
+/// wait_group.wait_async().await;
+/// # }
+/// ```
+pub struct WaitGroup {
+    // The number of `WaitGroup`s that haven't been dropped.
+    count: Arc<AtomicI32>,
+}
+
+impl Default for WaitGroup {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WaitGroup {
+    pub fn new() -> Self {
+        Self {
+            count: Arc::new(AtomicI32::new(1)),
+        }
+    }
+
+    /// Drops this `WaitGroup` and blocks until every clone of it has been dropped.
+    pub fn wait(self) {
+        let count = self.count.clone();
+        drop(self);
+        loop {
+            let value = count.load(Ordering::SeqCst);
+            if value == 0 {
+                return;
+            }
+            wait(&count, value);
+        }
+    }
+
+    /// Like `wait` but waits without blocking.
+    pub async fn wait_async(self) {
+        let count = self.count.clone();
+        drop(self);
+        loop {
+            let value = count.load(Ordering::SeqCst);
+            if value == 0 {
+                return;
+            }
+            wait_async(&count, value).await;
+        }
+    }
+}
+
+impl Clone for WaitGroup {
+    fn clone(&self) -> Self {
+        self.count.fetch_add(1, Ordering::SeqCst);
+        Self {
+            count: self.count.clone(),
+        }
+    }
+}
+
+impl Drop for WaitGroup {
+    fn drop(&mut self) {
+        if self.count.fetch_sub(1, Ordering::SeqCst) == 1 {
+            wake(&self.count, u32::MAX);
+        }
+    }
+}
@@ -0,0 +1,205 @@
+//! Multi-producer, multi-consumer channels.
+//!
+//! `recv` blocks in workers, and spins on the main thread which can't block.
+//! Use `recv_async` on the main thread instead.
+
+use super::*;
+use std::collections::VecDeque;
+use std::sync::atomic::AtomicUsize;
+
+pub use std::sync::mpsc::{RecvError, SendError, TryRecvError, TrySendError};
+
+struct Channel<T> {
+    queue: Mutex<VecDeque<T>>,
+    // `None` for unbounded channels.
+    capacity: Option<usize>,
+    senders: AtomicUsize,
+    receivers: AtomicUsize,
+    // Changed whenever a value is sent or the last `Sender` is dropped.
+    sent: AtomicI32,
+    // Changed whenever a value is received or the last `Receiver` is dropped.
+    received: AtomicI32,
+}
+
+/// Sends values to a channel. Can be cloned to send from multiple threads.
+pub struct Sender<T> {
+    channel: Arc<Channel<T>>,
+}
+
+/// Receives values from a channel. Can be cloned to receive on multiple threads.
+/// Each value is received by only one `Receiver`.
+pub struct Receiver<T> {
+    channel: Arc<Channel<T>>,
+}
+
+/// Creates a channel that can hold any number of values.
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    new_channel(None)
+}
+
+/// Creates a channel that holds at most `bound` values.
+/// Sending to a full channel waits until a value is received.
+///
+/// Unlike `std::sync::mpsc::sync_channel` this panics if `bound` is 0.
+pub fn sync_channel<T>(bound: usize) -> (Sender<T>, Receiver<T>) {
+    assert!(bound > 0, "sync_channel's bound must be at least 1");
+    new_channel(Some(bound))
+}
+
+fn new_channel<T>(capacity: Option<usize>) -> (Sender<T>, Receiver<T>) {
+    let channel = Arc::new(Channel {
+        queue: Mutex::new(VecDeque::new()),
+        capacity,
+        senders: AtomicUsize::new(1),
+        receivers: AtomicUsize::new(1),
+        sent: AtomicI32::new(0),
+        received: AtomicI32::new(0),
+    });
+    (
+        Sender {
+            channel: channel.clone(),
+        },
+        Receiver { channel },
+    )
+}
+
+impl<T> Channel<T> {
+    fn try_send(&self, value: T) -> Result<(), TrySendError<T>> {
+        if self.receivers.load(Ordering::SeqCst) == 0 {
+            return Err(TrySendError::Disconnected(value));
+        }
+
+        let mut queue = lock(&self.queue);
+        if let Some(capacity) = self.capacity {
+            if queue.len() >= capacity {
+                return Err(TrySendError::Full(value));
+            }
+        }
+        queue.push_back(value);
+        drop(queue);
+
+        notify_all(&self.sent);
+        Ok(())
+    }
+
+    fn try_recv(&self) -> Result<T, TryRecvError> {
+        // Check for senders first, every value they sent is queued by the time they're dropped.
+        let disconnected = self.senders.load(Ordering::SeqCst) == 0;
+
+        let value = lock(&self.queue).pop_front();
+        match value {
+            Some(value) => {
+                notify_all(&self.received);
+                Ok(value)
+            }
+            None if disconnected => Err(TryRecvError::Disconnected),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+}
+
+impl<T> Sender<T> {
+    /// Sends `value`, waiting for space if the channel is bounded and full.
+    /// Fails if every `Receiver` has been dropped.
+    pub fn send(&self, value: T) -> Result<(), SendError<T>> {
+        let mut value = value;
+        loop {
+            // Read the state first so a value received after checking isn't missed.
+            let received = self.channel.received.load(Ordering::SeqCst);
+            match self.channel.try_send(value) {
+                Ok(()) => return Ok(()),
+                Err(TrySendError::Disconnected(v)) => return Err(SendError(v)),
+                Err(TrySendError::Full(v)) => value = v,
+            }
+            wait(&self.channel.received, received);
+        }
+    }
+
+    /// Like `send` but waits for space without blocking.
+    pub async fn send_async(&self, value: T) -> Result<(), SendError<T>> {
+        let mut value = value;
+        loop {
+            let received = self.channel.received.load(Ordering::SeqCst);
+            match self.channel.try_send(value) {
+                Ok(()) => return Ok(()),
+                Err(TrySendError::Disconnected(v)) => return Err(SendError(v)),
+                Err(TrySendError::Full(v)) => value = v,
+            }
+            wait_async(&self.channel.received, received).await;
+        }
+    }
+
+    /// Sends `value` if there's space for it.
+    pub fn try_send(&self, value: T) -> Result<(), TrySendError<T>> {
+        self.channel.try_send(value)
+    }
+}
+
+impl<T> Receiver<T> {
+    /// Waits for a value.
+    /// Fails once the channel is empty and every `Sender` has been dropped.
+    pub fn recv(&self) -> Result<T, RecvError> {
+        loop {
+            // Read the state first so a value sent after checking isn't missed.
+            let sent = self.channel.sent.load(Ordering::SeqCst);
+            match self.channel.try_recv() {
+                Ok(value) => return Ok(value),
+                Err(TryRecvError::Disconnected) => return Err(RecvError),
+                Err(TryRecvError::Empty) => {}
+            }
+            wait(&self.channel.sent, sent);
+        }
+    }
+
+    /// Like `recv` but waits without blocking.
+    pub async fn recv_async(&self) -> Result<T, RecvError> {
+        loop {
+            let sent = self.channel.sent.load(Ordering::SeqCst);
+            match self.channel.try_recv() {
+                Ok(value) => return Ok(value),
+                Err(TryRecvError::Disconnected) => return Err(RecvError),
+                Err(TryRecvError::Empty) => {}
+            }
+            wait_async(&self.channel.sent, sent).await;
+        }
+    }
+
+    /// Returns a value if one is ready.
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        self.channel.try_recv()
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.channel.senders.fetch_add(1, Ordering::SeqCst);
+        Self {
+            channel: self.channel.clone(),
+        }
+    }
+}
+
+impl<T> Clone for Receiver<T> {
+    fn clone(&self) -> Self {
+        self.channel.receivers.fetch_add(1, Ordering::SeqCst);
+        Self {
+            channel: self.channel.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        if self.channel.senders.fetch_sub(1, Ordering::SeqCst) == 1 {
+            notify_all(&self.channel.sent);
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        if self.channel.receivers.fetch_sub(1, Ordering::SeqCst) == 1 {
+            notify_all(&self.channel.received);
+        }
+    }
+}
@@ -0,0 +1,45 @@
+//! Multi-producer, single-consumer channels.
+//!
+//! These are `mpmc` channels whose `Receiver` can't be cloned.
+
+use super::mpmc;
+
+pub use mpmc::{RecvError, SendError, Sender, TryRecvError, TrySendError};
+
+/// Receives values from a channel.
+pub struct Receiver<T> {
+    inner: mpmc::Receiver<T>,
+}
+
+/// Creates a channel that can hold any number of values.
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    let (sender, inner) = mpmc::channel();
+    (sender, Receiver { inner })
+}
+
+/// Creates a channel that holds at most `bound` values.
+/// Sending to a full channel waits until a value is received.
+///
+/// Unlike `std::sync::mpsc::sync_channel` this panics if `bound` is 0.
+pub fn sync_channel<T>(bound: usize) -> (Sender<T>, Receiver<T>) {
+    let (sender, inner) = mpmc::sync_channel(bound);
+    (sender, Receiver { inner })
+}
+
+impl<T> Receiver<T> {
+    /// Waits for a value.
+    /// Fails once the channel is empty and every `Sender` has been dropped.
+    pub fn recv(&self) -> Result<T, RecvError> {
+        self.inner.recv()
+    }
+
+    /// Like `recv` but waits without blocking.
+    pub async fn recv_async(&self) -> Result<T, RecvError> {
+        self.inner.recv_async().await
+    }
+
+    /// Returns a value if one is ready.
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        self.inner.try_recv()
+    }
+}
@@ -0,0 +1,75 @@
+use crate::*;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+static NEXT_TRANSFER_ID: AtomicU32 = AtomicU32::new(1);
+
+thread_local! {
+    static TRANSFER_SEND: JSObjectFromString = JSObjectFromString::new("self.kwasm_transfer_send");
+    static TRANSFER_RECEIVE: JSObjectFromString =
+        JSObjectFromString::new("self.kwasm_transfer_receive");
+    static TRANSFER_DISCARD: JSObjectFromString =
+        JSObjectFromString::new("self.kwasm_transfer_discard");
+}
+
+/// A Javascript object that can be sent to another thread.
+///
+/// `JSObject`s are handles that only mean something on the thread that created them.
+/// `new` moves the object out of the current thread like `postMessage` does, and `into_local`
+/// makes it a `JSObjectDynamic` on whichever thread the `TransferableJSObject` was sent to.
+///
+/// Objects are passed through the main thread, so its event loop must be running.
+///
+/// ```no_run
+/// # use kwasm::*;
+/// # async fn f(canvas: JSObjectDynamic) {
+/// let offscreen_canvas = canvas.call_method("transferControlToOffscreen", &[]).unwrap().unwrap();
+/// let offscreen_canvas = TransferableJSObject::new(&offscreen_canvas).unwrap();
+/// web_worker::spawn(move || {
+///     spawn_local(async move {
+///         let offscreen_canvas = offscreen_canvas.into_local().await.unwrap();
+///         // Render to the canvas.
+///     });
+/// });
+/// # }
+/// ```
+pub struct TransferableJSObject {
+    id: u32,
+    received: bool,
+}
+
+impl TransferableJSObject {
+    /// Moves `object` out of this thread.
+    ///
+    /// Objects that can be transferred, like an `ArrayBuffer`, `MessagePort`, or `OffscreenCanvas`,
+    /// can't be used on this thread afterwards. Other objects are copied with structured clone.
+    /// Returns an error if the object can't be transferred or cloned.
+    pub fn new(object: &JSObject) -> Result<Self, JSError> {
+        let id = NEXT_TRANSFER_ID.fetch_add(1, Ordering::Relaxed);
+        TRANSFER_SEND
+            .with(|f| f.call_typed(&JSObject::NULL, &[id.into_js_arg(), object.into_js_arg()]))?;
+        Ok(Self {
+            id,
+            received: false,
+        })
+    }
+
+    /// Waits for the object to arrive on the current thread.
+    /// Returns an error if it couldn't be delivered.
+    pub async fn into_local(mut self) -> Result<JSObjectDynamic, JSError> {
+        self.received = true;
+        let promise = TRANSFER_RECEIVE
+            .with(|f| f.call_typed(&JSObject::NULL, &[self.id.into_js_arg()]))?
+            .unwrap();
+        JSFuture::new(&promise).await
+    }
+}
+
+impl Drop for TransferableJSObject {
+    fn drop(&mut self) {
+        // Otherwise the main thread holds on to the object forever.
+        if !self.received {
+            let _ =
+                TRANSFER_DISCARD.with(|f| f.call_typed(&JSObject::NULL, &[self.id.into_js_arg()]));
+        }
+    }
+}
@@ -1,11 +1,31 @@
 use crate::*;
-use std::usize;
+use std::any::Any;
+use std::cell::RefCell;
+use std::fmt;
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::{Arc, Mutex};
 #[allow(unused)]
 use wasm_set_stack_pointer;
 
+mod main_thread;
+mod worker_pool;
+pub use main_thread::*;
+pub use worker_pool::*;
+
 #[allow(unused)]
 const WASM_PAGE_SIZE: usize = 1024 * 64;
 
+const DEFAULT_STACK_SIZE: usize = 1 << 20; // 1 MB stack size.
+
+// The low end of each worker's stack is filled with this value.
+// If any of it changes the stack has overflowed.
+const STACK_GUARD_SIZE: usize = 4096;
+const STACK_GUARD_VALUE: u32 = 0x57AC_6A4D;
+// Checks that run after every task or Javascript call only read every this many words of the guard.
+// The stack grows down, so an overflow reaches the guard's top word first.
+const STACK_GUARD_SAMPLE_STRIDE: usize = 64;
+
 struct WorkerData {
     entry_point: Option<Box<dyn FnOnce() + Send + 'static>>,
     #[allow(unused)]
@@ -42,28 +62,374 @@ impl Drop for WorkerData {
     }
 }
 
-pub fn spawn<F>(f: F)
+// The values of `JoinState::state`.
+// The host also sets `PANICKED` if a job traps, see `kwasm_push_join_state`.
+const RUNNING: i32 = 0;
+const FINISHED: i32 = 1;
+const PANICKED: i32 = 2;
+
+/// Shared between a worker and its `JoinHandle`.
+struct JoinState {
+    state: AtomicI32,
+    panic_message: Mutex<Option<String>>,
+}
+
+impl JoinState {
+    fn set(&self, state: i32) {
+        if self
+            .state
+            .compare_exchange(RUNNING, state, Ordering::SeqCst, Ordering::SeqCst)
+            .is_ok()
+        {
+            unsafe {
+                core::arch::wasm32::memory_atomic_notify(self.state_pointer(), u32::MAX);
+            }
+        }
+    }
+
+    fn state_pointer(&self) -> *mut i32 {
+        &self.state as *const AtomicI32 as *mut i32
+    }
+}
+
+thread_local! {
+    // Set for the duration of a job. A job run inside another job restores the outer job's state.
+    static CURRENT_JOIN_STATE: RefCell<Option<Arc<JoinState>>> = const { RefCell::new(None) };
+    static IS_WORKER: std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
+    static CURRENT_THREAD_NAME: RefCell<Option<String>> = const { RefCell::new(None) };
+    // The low end of this worker's stack, or 0 if it has no guard region.
+    static STACK_GUARD: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+    // Set once this worker's entry point has returned or panicked.
+    static ENTRY_POINT_RETURNED: std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
+    static EXITING: std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
+}
+
+/// Called by the panic hook so the panic's message can be returned from `join`.
+pub(crate) fn record_panic(message: &str) {
+    let _ = CURRENT_JOIN_STATE.try_with(|join_state| {
+        if let Some(join_state) = join_state.borrow().as_ref() {
+            *join_state.panic_message.lock().unwrap() = Some(message.to_string());
+            join_state.set(PANICKED);
+        }
+    });
+}
+
+#[allow(unused)]
+unsafe fn fill_stack_guard(stack_memory: *mut u8) {
+    let guard = stack_memory as *mut u32;
+    for i in 0..STACK_GUARD_SIZE / 4 {
+        guard.add(i).write_volatile(STACK_GUARD_VALUE);
+    }
+}
+
+/// Returns a message describing the overflow if this worker's stack has overflowed.
+/// The overflow is only reported once, so reporting it can't trigger another report.
+pub(crate) fn take_stack_overflow_message() -> Option<String> {
+    take_stack_overflow_message_with_stride(1)
+}
+
+fn take_stack_overflow_message_with_stride(stride: usize) -> Option<String> {
+    let guard = STACK_GUARD.try_with(|g| g.get()).unwrap_or(0) as *const u32;
+    if guard.is_null() {
+        return None;
+    }
+
+    let words = STACK_GUARD_SIZE / 4;
+    let intact = (0..words)
+        .rev()
+        .step_by(stride)
+        .all(|i| unsafe { guard.add(i).read_volatile() } == STACK_GUARD_VALUE);
+    if intact {
+        None
+    } else {
+        STACK_GUARD.with(|g| g.set(0));
+        Some(match current_thread_name() {
+            Some(name) => format!("stack overflow in worker \"{}\"", name),
+            None => "stack overflow in unnamed worker".to_string(),
+        })
+    }
+}
+
+/// Panics if the current worker's stack has overflowed into the guard region at its low end.
+///
+/// The stack is checked when the worker's entry point returns and when a panic occurs.
+/// The guard is also sampled between executor tasks, and in debug builds after each call into Javascript.
+/// Use `Builder::stack_size` to give a worker a larger stack.
+/// This does nothing on the main thread or when using `wasm-bindgen`.
+pub fn check_stack_overflow() {
+    if let Some(message) = take_stack_overflow_message() {
+        panic!("{}. The rest of the Wasm heap may be corrupted.", message);
+    }
+}
+
+/// A cheaper `check_stack_overflow` for the executor and Javascript calls that only samples the guard.
+pub(crate) fn check_stack_overflow_sampled() {
+    if let Some(message) = take_stack_overflow_message_with_stride(STACK_GUARD_SAMPLE_STRIDE) {
+        panic!("{}. The rest of the Wasm heap may be corrupted.", message);
+    }
+}
+
+/// Exits this worker once its entry point has returned and it has no `spawn_local` tasks left.
+///
+/// The worker's stack and thread local storage can't be freed while it's running, so the
+/// host waits for the worker to return to its event loop, closes it, and then the thread that
+/// spawned the worker frees its memory and terminates it.
+/// Closing a worker terminates the workers it spawned, so the host first waits for them to exit.
+pub(crate) fn exit_if_finished() {
+    if !ENTRY_POINT_RETURNED.with(|r| r.get()) || crate::executor::local_task_count() > 0 {
+        return;
+    }
+    if EXITING.with(|e| e.replace(true)) {
+        return;
+    }
+
+    // The standard library has no way to run `thread_local!` destructors for a Web Worker,
+    // so kwasm's own per-thread state is dropped here. Other thread locals are leaked, see `spawn`.
+    crate::executor::drop_local_tasks();
+    CURRENT_JOIN_STATE.with(|c| *c.borrow_mut() = None);
+    CURRENT_THREAD_NAME.with(|n| *n.borrow_mut() = None);
+    STACK_GUARD.with(|g| g.set(0));
+    DATA_FROM_HOST.with(|d| *d.borrow_mut() = Vec::new());
+
+    unsafe {
+        kwasm_exit_worker();
+    }
+}
+
+/// The worker panicked before its entry point returned.
+#[derive(Debug, Clone)]
+pub struct JoinError {
+    /// The panic's message, if it could be recovered.
+    pub message: Option<String>,
+}
+
+impl fmt::Display for JoinError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.message {
+            Some(message) => write!(f, "worker panicked: {}", message),
+            None => write!(f, "worker panicked"),
+        }
+    }
+}
+
+impl std::error::Error for JoinError {}
+
+/// Waits for a worker started with `spawn` to finish and gets its result.
+pub struct JoinHandle<T> {
+    join_state: Arc<JoinState>,
+    result: Arc<Mutex<Option<T>>>,
+}
+
+impl<T> JoinHandle<T> {
+    /// Returns true once the worker's entry point has returned or panicked.
+    pub fn is_finished(&self) -> bool {
+        self.join_state.state.load(Ordering::SeqCst) != RUNNING
+    }
+
+    /// Blocks until the worker finishes.
+    ///
+    /// The main thread isn't allowed to block, so this panics if called from the main thread.
+    /// Use `join_async` there instead.
+    pub fn join(self) -> Result<T, JoinError> {
+        if !IS_WORKER.with(|w| w.get()) {
+            panic!("JoinHandle::join cannot block on the main thread. Use `join_async` instead.");
+        }
+        while !self.is_finished() {
+            unsafe {
+                core::arch::wasm32::memory_atomic_wait32(
+                    self.join_state.state_pointer(),
+                    RUNNING,
+                    -1,
+                );
+            }
+        }
+        self.take_result()
+    }
+
+    /// Waits for the worker to finish without blocking.
+    pub async fn join_async(self) -> Result<T, JoinError> {
+        while !self.is_finished() {
+            let _ = crate::executor::wait_async(&self.join_state.state, RUNNING).await;
+        }
+        self.take_result()
+    }
+
+    fn take_result(self) -> Result<T, JoinError> {
+        match self.join_state.state.load(Ordering::SeqCst) {
+            FINISHED => Ok(self.result.lock().unwrap().take().unwrap()),
+            _ => Err(JoinError {
+                message: self.join_state.panic_message.lock().unwrap().take(),
+            }),
+        }
+    }
+}
+
+fn panic_payload_message(payload: &(dyn Any + Send)) -> Option<String> {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        Some(message.to_string())
+    } else {
+        payload.downcast_ref::<String>().cloned()
+    }
+}
+
+/// Runs `f` on a new Web Worker with the default settings.
+/// The returned `JoinHandle` can be used to wait for the worker to finish and get `f`'s result.
+///
+/// The worker exits once `f` returns, any tasks it started with `spawn_local` complete,
+/// and any workers it spawned have exited.
+/// Its stack and thread local storage are then freed by the thread that spawned it.
+///
+/// The standard library can't run `thread_local!` destructors on a Web Worker, so values
+/// `f` stores in thread locals are never dropped. Anything they own on the heap is leaked
+/// when the worker exits.
+pub fn spawn<F, T>(f: F) -> JoinHandle<T>
 where
-    F: FnOnce() + Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
 {
-    let f = Box::new(f) as Box<dyn FnOnce() + Send + 'static>;
+    Builder::new().spawn(f)
+}
+
+/// Returns true on a worker started by `spawn`.
+pub(crate) fn is_worker() -> bool {
+    IS_WORKER.with(|w| w.get())
+}
+
+/// Returns the name of the current worker if it was given one with `Builder::name`.
+/// Returns `None` on the main thread.
+pub fn current_thread_name() -> Option<String> {
+    CURRENT_THREAD_NAME.with(|n| n.borrow().clone())
+}
+
+/// Configures a Web Worker before it's spawned.
+///
+/// ```no_run
+/// let worker = kwasm::web_worker::Builder::new()
+///     .stack_size(8 << 20)
+///     .name("decoder")
+///     .spawn(|| 42);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Builder {
+    stack_size: usize,
+    name: Option<String>,
+}
 
-    let stack_size = 1 << 20; // 1 MB stack size.
+impl Default for Builder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Builder {
+    pub fn new() -> Self {
+        Self {
+            stack_size: DEFAULT_STACK_SIZE,
+            name: None,
+        }
+    }
+
+    /// Sets the size of the worker's stack in bytes. The default is 1 MB.
+    /// This is ignored when using `wasm-bindgen`, which allocates worker stacks itself.
+    pub fn stack_size(mut self, stack_size: usize) -> Self {
+        self.stack_size = stack_size;
+        self
+    }
+
+    /// Sets the worker's name.
+    /// The name is passed to `new Worker` so it shows up in the browser's devtools.
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = Some(name.to_string());
+        self
+    }
+
+    /// Runs `f` on a new Web Worker.
+    /// The returned `JoinHandle` can be used to wait for the worker to finish and get `f`'s result.
+    pub fn spawn<F, T>(self, f: F) -> JoinHandle<T>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        spawn_inner(f, self.stack_size, self.name)
+    }
+}
+
+/// Wraps `f` so that running it on a worker completes the returned `JoinHandle`.
+fn new_job<F, T>(f: F) -> (Box<dyn FnOnce() + Send + 'static>, JoinHandle<T>)
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let join_state = Arc::new(JoinState {
+        state: AtomicI32::new(RUNNING),
+        panic_message: Mutex::new(None),
+    });
+    let result = Arc::new(Mutex::new(None));
+
+    let join_handle = JoinHandle {
+        join_state: join_state.clone(),
+        result: result.clone(),
+    };
+
+    let job = Box::new(move || {
+        // The host only holds the pointer until it's popped, and `previous` keeps the
+        // outer job's state alive until then.
+        let previous = CURRENT_JOIN_STATE.with(|c| c.replace(Some(join_state.clone())));
+        unsafe {
+            kwasm_push_join_state(join_state.state_pointer() as u32);
+        }
+
+        // Without `panic = "abort"` the panic can be caught here.
+        // Otherwise the panic hook records the panic and the host catches the trap.
+        match std::panic::catch_unwind(AssertUnwindSafe(f)) {
+            Ok(value) => {
+                check_stack_overflow();
+                *result.lock().unwrap() = Some(value);
+                join_state.set(FINISHED);
+            }
+            Err(payload) => {
+                let mut panic_message = join_state.panic_message.lock().unwrap();
+                if panic_message.is_none() {
+                    *panic_message = panic_payload_message(&*payload);
+                }
+                drop(panic_message);
+                join_state.set(PANICKED);
+            }
+        }
+
+        CURRENT_JOIN_STATE.with(|c| *c.borrow_mut() = previous);
+        unsafe {
+            kwasm_pop_join_state();
+        }
+    });
+    (job, join_handle)
+}
+
+fn spawn_inner<F, T>(f: F, stack_size: usize, name: Option<String>) -> JoinHandle<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    // Keep the stack pointer aligned, and make room for the guard region.
+    let stack_size = ((stack_size + 15) & !15) + STACK_GUARD_SIZE;
+    let name_for_host = name.clone().unwrap_or_default();
+
+    let (job, join_handle) = new_job(f);
 
     #[cfg(not(feature = "wasm_bindgen_support"))]
     let (stack_memory, stack_pointer, thread_local_storage_memory) = unsafe {
         let stack_layout =
             core::alloc::Layout::from_size_align(stack_size, WASM_PAGE_SIZE).unwrap();
         // The stack pointer should be set to the other end.
-        // An unfortunate consequence of this design is that a stack overflow will just corrupt the rest of the WASM heap.
-        // I suppose a warning buffer of sorts could be set and checked from time to time, but
-        // that's not worth implementing now.
+        // A stack overflow will write past the low end of the stack into the rest of the WASM heap,
+        // so the low end is filled with a guard region that's checked for changes.
         let stack_memory = std::alloc::alloc(stack_layout);
+        fill_stack_guard(stack_memory);
 
         let thread_local_storage_memory = kwasm_alloc_thread_local_storage() as *mut u8;
         (
             stack_memory,
-            stack_memory.offset(stack_size as isize),
+            stack_memory.add(stack_size),
             thread_local_storage_memory,
         )
     };
@@ -77,6 +443,17 @@ where
         std::ptr::null_mut(),
     );
 
+    let stack_guard = stack_memory as usize;
+
+    let f = Box::new(move || {
+        IS_WORKER.with(|w| w.set(true));
+        CURRENT_THREAD_NAME.with(|n| *n.borrow_mut() = name);
+        STACK_GUARD.with(|g| g.set(stack_guard));
+        job();
+        ENTRY_POINT_RETURNED.with(|r| r.set(true));
+        exit_if_finished();
+    }) as Box<dyn FnOnce() + Send + 'static>;
+
     let worker_data = Box::new(WorkerData {
         entry_point: Some(f),
         stack_memory,
@@ -89,14 +466,30 @@ where
             Box::leak(worker_data) as *mut _ as *mut std::ffi::c_void as u32,
             stack_pointer as *mut std::ffi::c_void as u32,
             thread_local_storage_memory as *mut std::ffi::c_void as u32,
+            name_for_host.as_ptr(),
+            name_for_host.len() as u32,
         );
     }
+
+    join_handle
 }
 
 #[no_mangle]
 extern "C" fn kwasm_web_worker_entry_point(callback: u32) {
+    // The worker is still running on the stack owned by its `WorkerData`,
+    // so it's freed later by `kwasm_free_worker`.
+    unsafe {
+        let worker_data = callback as *mut std::ffi::c_void as *mut WorkerData;
+        ((*worker_data).entry_point.take().unwrap())()
+    }
+}
+
+/// Called by the host on the thread that spawned a worker after the worker has exited.
+#[no_mangle]
+extern "C" fn kwasm_free_worker(callback: u32) {
     unsafe {
-        let mut b: Box<WorkerData> = Box::from_raw(callback as *mut std::ffi::c_void as *mut _);
-        (b.entry_point.take().unwrap())()
+        drop(Box::from_raw(
+            callback as *mut std::ffi::c_void as *mut WorkerData,
+        ));
     }
 }
@@ -0,0 +1,77 @@
+use super::*;
+use std::collections::VecDeque;
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+// Closures waiting to run on the main thread.
+static MAIN_THREAD_QUEUE: Mutex<VecDeque<Job>> = Mutex::new(VecDeque::new());
+
+/// Runs `f` on the main thread and returns its result.
+///
+/// Javascript objects can't be shared between threads, so this is how a worker can use
+/// Web APIs that are only available on the main thread, like `document`.
+///
+/// In a worker this blocks until `f` has run, which requires the main thread's event loop to
+/// be running. On the main thread `f` is called immediately.
+/// Panics if `f` panics.
+pub fn run_on_main_thread<F, T>(f: F) -> T
+where
+    F: FnOnce() -> T + Send,
+    T: Send + 'static,
+{
+    if !IS_WORKER.with(|w| w.get()) {
+        return f();
+    }
+
+    let f: Box<dyn FnOnce() -> T + Send + '_> = Box::new(f);
+    // Safety: `f` is dropped before the job completes, and this blocks until then,
+    // so `f` can't outlive anything it borrows.
+    let f: Box<dyn FnOnce() -> T + Send + 'static> = unsafe { std::mem::transmute(f) };
+
+    let (job, join_handle) = new_job(f);
+    push_job(job);
+    match join_handle.join() {
+        Ok(value) => value,
+        Err(error) => panic!("run_on_main_thread: {}", error),
+    }
+}
+
+/// Runs `f` on the main thread and waits for its result without blocking.
+/// On the main thread `f` is called immediately.
+/// Panics if `f` panics.
+pub async fn run_on_main_thread_async<F, T>(f: F) -> T
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    if !IS_WORKER.with(|w| w.get()) {
+        return f();
+    }
+
+    let (job, join_handle) = new_job(f);
+    push_job(job);
+    match join_handle.join_async().await {
+        Ok(value) => value,
+        Err(error) => panic!("run_on_main_thread_async: {}", error),
+    }
+}
+
+fn push_job(job: Job) {
+    crate::executor::lock(&MAIN_THREAD_QUEUE).push_back(job);
+    unsafe {
+        kwasm_nudge_main_thread();
+    }
+}
+
+/// Called by the host on the main thread after a worker queues a closure.
+#[no_mangle]
+extern "C" fn kwasm_run_main_thread_queue() {
+    loop {
+        // Don't hold the lock while running the job, it may queue another.
+        let job = crate::executor::lock(&MAIN_THREAD_QUEUE).pop_front();
+        match job {
+            Some(job) => job(),
+            None => return,
+        }
+    }
+}
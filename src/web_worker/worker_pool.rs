@@ -0,0 +1,114 @@
+use super::*;
+use std::collections::VecDeque;
+use std::sync::atomic::AtomicBool;
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+struct PoolState {
+    // Changed whenever a job is queued or the pool shuts down so parked workers wake up.
+    state: AtomicI32,
+    jobs: Mutex<VecDeque<Job>>,
+    shutdown: AtomicBool,
+}
+
+/// A fixed set of Web Workers that run closures passed to `spawn`.
+///
+/// Starting a worker instantiates the Wasm module again, which is slow.
+/// A `WorkerPool`'s workers are started once and reuse their stack and thread local storage
+/// for every closure. Idle workers are parked with `Atomics.wait`.
+///
+/// Each closure runs to completion before the worker takes the next one, and the worker's
+/// Javascript event loop doesn't run while it's parked. Use `executor::spawn` for futures.
+///
+/// Dropping the pool lets its workers exit once the closures already passed to `spawn` have run.
+pub struct WorkerPool {
+    pool_state: Arc<PoolState>,
+}
+
+impl WorkerPool {
+    /// Starts `count` workers with the default settings.
+    ///
+    /// Panics if `count` is 0, because closures passed to `spawn` would never run.
+    pub fn new(count: usize) -> Self {
+        Self::with_builder(count, Builder::new().name("kwasm pool worker"))
+    }
+
+    /// Starts `count` workers configured by `builder`.
+    /// Each worker's name is the builder's name followed by the worker's index.
+    ///
+    /// Panics if `count` is 0.
+    pub fn with_builder(count: usize, builder: Builder) -> Self {
+        assert!(count > 0, "a WorkerPool needs at least one worker");
+        let pool_state = Arc::new(PoolState {
+            state: AtomicI32::new(0),
+            jobs: Mutex::new(VecDeque::new()),
+            shutdown: AtomicBool::new(false),
+        });
+
+        for index in 0..count {
+            let name = match &builder.name {
+                Some(name) => format!("{} {}", name, index),
+                None => index.to_string(),
+            };
+            let pool_state = pool_state.clone();
+            builder
+                .clone()
+                .name(&name)
+                .spawn(move || run_pool_worker(&pool_state));
+        }
+
+        Self { pool_state }
+    }
+
+    /// Runs `f` on the first idle worker.
+    /// The returned `JoinHandle` can be used to wait for `f` to finish and get its result.
+    ///
+    /// If `f` panics with `panic = "abort"` its worker is lost and the pool has one fewer worker.
+    pub fn spawn<F, T>(&self, f: F) -> JoinHandle<T>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let (job, join_handle) = new_job(f);
+        crate::executor::lock(&self.pool_state.jobs).push_back(job);
+        crate::executor::notify(&self.pool_state.state);
+        join_handle
+    }
+}
+
+impl Drop for WorkerPool {
+    fn drop(&mut self) {
+        self.pool_state.shutdown.store(true, Ordering::SeqCst);
+        self.pool_state.state.fetch_add(1, Ordering::SeqCst);
+        unsafe {
+            core::arch::wasm32::memory_atomic_notify(
+                &self.pool_state.state as *const AtomicI32 as *mut i32,
+                u32::MAX,
+            );
+        }
+    }
+}
+
+fn run_pool_worker(pool_state: &PoolState) {
+    loop {
+        // Read the state first so a job queued after the queue is checked isn't missed.
+        let state = pool_state.state.load(Ordering::SeqCst);
+
+        let job = crate::executor::lock(&pool_state.jobs).pop_front();
+        if let Some(job) = job {
+            job();
+            continue;
+        }
+        if pool_state.shutdown.load(Ordering::SeqCst) {
+            return;
+        }
+
+        unsafe {
+            core::arch::wasm32::memory_atomic_wait32(
+                &pool_state.state as *const AtomicI32 as *mut i32,
+                state,
+                -1,
+            );
+        }
+    }
+}